@@ -0,0 +1,149 @@
+import asyncio
+
+# A frame with this size closes the channel instead of carrying a payload
+CLOSE = 2**64 - 1
+
+# Multiplexed connections start with this preamble; anything else is a single
+# length-prefixed request, which every protocol version understands
+PREAMBLE = b'KIROSHI\x01'
+
+MAX_FRAME_SIZE = 2**30
+
+
+class Channel:
+    def __init__(self, id: int, writer: asyncio.StreamWriter):
+        self.id = id
+        self.writer = writer
+        self.frames = asyncio.Queue()
+        self.closed = False
+
+    async def read(self):
+        frame = await self.frames.get()
+
+        if frame is None:
+            raise asyncio.IncompleteReadError(b'', None)
+
+        return frame
+
+    def write(self, data):
+        if self.is_closing():
+            return
+
+        self.writer.write(header(self.id, len(data)))
+        self.writer.write(data)
+
+    async def drain(self):
+        if self.is_closing():
+            raise ConnectionResetError(f"Channel {self.id} is closed")
+
+        await self.writer.drain()
+
+    def is_closing(self):
+        return self.closed or self.writer.is_closing()
+
+    def close(self):
+        if not self.is_closing():
+            self.writer.write(header(self.id, CLOSE))
+
+        self.closed = True
+
+    async def wait_closed(self):
+        if not self.writer.is_closing():
+            await self.writer.drain()
+
+    def feed(self, frame):
+        self.frames.put_nowait(frame)
+
+    def feed_eof(self):
+        self.closed = True
+        self.frames.put_nowait(None)
+
+
+class Single:
+    def __init__(self, reader: asyncio.StreamReader, writer: asyncio.StreamWriter):
+        self.reader = reader
+        self.writer = writer
+
+    async def read(self):
+        size = int.from_bytes(await self.reader.readexactly(8), "big", signed=False)
+        return await read_payload(self.reader, size)
+
+    def write(self, data):
+        self.writer.write(int.to_bytes(len(data), 8, "big", signed=False))
+        self.writer.write(data)
+
+    async def drain(self):
+        await self.writer.drain()
+
+    def is_closing(self):
+        return self.writer.is_closing()
+
+    def close(self):
+        self.writer.close()
+
+    async def wait_closed(self):
+        await self.writer.wait_closed()
+
+
+async def accept(reader: asyncio.StreamReader, writer: asyncio.StreamWriter, serve):
+    try:
+        preamble = await reader.readexactly(8)
+
+        if preamble == PREAMBLE:
+            await multiplex(reader, writer, serve)
+        else:
+            size = int.from_bytes(preamble, "big", signed=False)
+            data = await read_payload(reader, size)
+
+            await serve(Single(reader, writer), data)
+    except (asyncio.IncompleteReadError, ConnectionError):
+        writer.close()
+
+
+async def multiplex(reader: asyncio.StreamReader, writer: asyncio.StreamWriter, serve):
+    channels = {}
+    tasks = set()
+
+    try:
+        while True:
+            id = int.from_bytes(await reader.readexactly(8), "big", signed=False)
+            size = int.from_bytes(await reader.readexactly(8), "big", signed=False)
+
+            if size == CLOSE:
+                channel = channels.pop(id, None)
+
+                if not channel is None:
+                    channel.feed_eof()
+
+                continue
+
+            data = await read_payload(reader, size)
+            channel = channels.get(id)
+
+            if channel is None:
+                channel = Channel(id, writer)
+                channels[id] = channel
+
+                task = asyncio.create_task(serve(channel, data))
+                tasks.add(task)
+                task.add_done_callback(tasks.discard)
+            else:
+                channel.feed(data)
+    except (asyncio.IncompleteReadError, ConnectionError):
+        pass
+    finally:
+        for channel in channels.values():
+            channel.feed_eof()
+
+        writer.close()
+
+
+async def read_payload(reader: asyncio.StreamReader, size: int):
+    if size > MAX_FRAME_SIZE:
+        raise ConnectionError(f"Frame of {size} bytes exceeds the limit of {MAX_FRAME_SIZE}")
+
+    return await reader.readexactly(size)
+
+
+def header(id: int, size: int):
+    return int.to_bytes(id, 8, "big", signed=False) + int.to_bytes(size, 8, "big", signed=False)
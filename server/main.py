@@ -1,6 +1,10 @@
 import text_to_image
+import transfer
+from channel import Channel, accept
+from scheduler import Job, Scheduler
 
 import asyncio
+import dataclasses
 import json
 import time
 import torch
@@ -8,65 +12,244 @@ import gc
 import multiprocessing
 import signal
 import os
-from PIL import ImageFilter
+from PIL import Image, ImageFilter
+
+VERSION = '0.1.0'
+PROTOCOL_VERSION = 3
+
+TASKS = [
+    'ping',
+    'generate_image',
+    'outpaint_image',
+    'detail_image',
+    'upscale_image',
+    'detect',
+    'list_models',
+    'list_loras',
+    'set_memory_mode',
+    'reprioritize_job',
+    'remove_job',
+]
+
+SAMPLERS = {
+    'euler_a': text_to_image.Sampler.EULER_A,
+    'dpm++_sde_karras': text_to_image.Sampler.DPM_SDE_KARRAS,
+    'dpm++_2m_karras': text_to_image.Sampler.DPM_2M_KARRAS,
+    'dpm++_2m_sde_karras': text_to_image.Sampler.DPM_2M_SDE_KARRAS,
+}
+
+UPSCALERS = {
+    '2x-real_esrgan': text_to_image.Upscaling.REAL_ESRGAN_2X,
+    '4x-ultrasharp': text_to_image.Upscaling.ULTRASHARP_4X,
+}
+
+scheduler = Scheduler()
+memory_mode = text_to_image.MemoryMode.GPU
 
 
 async def server():
-    server = await asyncio.start_server(instance, '0.0.0.0', 9149)
-    print("[kiroshi] Server started at 0.0.0.0:9149")
+    socket = os.environ.get('KIROSHI_SOCKET')
+
+    if socket:
+        server = await asyncio.start_unix_server(connection, socket)
+        print(f"[kiroshi] Server started at unix:{socket}")
+    else:
+        host = os.environ.get('KIROSHI_HOST') or '0.0.0.0'
+        port = int(os.environ.get('KIROSHI_PORT') or 9149)
+
+        server = await asyncio.start_server(connection, host, port)
+        print(f"[kiroshi] Server started at {host}:{port}")
 
     async with server:
         await server.serve_forever()
 
 
-async def instance(reader: asyncio.StreamReader, writer: asyncio.StreamWriter):
-    size = await reader.readexactly(8)
-    size = int.from_bytes(size, "big", signed=False)
+async def connection(reader: asyncio.StreamReader, writer: asyncio.StreamWriter):
+    await accept(reader, writer, instance)
 
-    message = await reader.readexactly(size)
-    message = json.loads(message)
+
+async def instance(channel: Channel, data):
+    message = json.loads(data)
 
     print(f"[kiroshi] Received: {message}")
 
+    # Each request gets its own channel, which acts as both reader and writer
+    (reader, writer) = (channel, channel)
+
+    try:
+        await handle(reader, writer, message)
+    except Exception as error:
+        print(f"[kiroshi] Request failed: {error!r}")
+
+        try:
+            await send_json(writer, {'error': describe(error)})
+        except ConnectionError:
+            pass
+
+    try:
+        writer.close()
+        await writer.wait_closed()
+    except ConnectionError:
+        pass
+
+
+def describe(error: Exception):
+    match error:
+        case text_to_image.ModelNotFound():
+            return {'kind': 'model_not_found', 'message': str(error), 'model': error.id}
+
+        case text_to_image.LoraNotFound():
+            return {'kind': 'lora_not_found', 'message': str(error), 'lora': error.id}
+
+        case torch.cuda.OutOfMemoryError():
+            return {'kind': 'out_of_memory', 'message': str(error)}
+
+        case Interrupt():
+            return {'kind': 'cancelled', 'message': "request was cancelled"}
+
+        case InvalidRequest() | KeyError() | ValueError():
+            return {'kind': 'invalid_request', 'message': str(error)}
+
+        case _:
+            return {'kind': 'internal', 'message': str(error)}
+
+
+async def handle(reader: Channel, writer: Channel, message):
     match message['task']:
         case 'ping':
-            await send_json(writer, True)
+            await send_json(writer, {
+                'version': VERSION,
+                'protocol': PROTOCOL_VERSION,
+                'tasks': TASKS,
+                'samplers': list(SAMPLERS),
+                'upscalers': list(UPSCALERS),
+                'detectors': [detector.value for detector in text_to_image.Detector],
+                'formats': transfer.FORMATS,
+            })
 
         case 'generate_image':
-            await generate_image(writer, message)
+            await generate_image(reader, writer, message)
+
+        case 'outpaint_image':
+            await outpaint_image(reader, writer, message)
+
+        case 'detail_image':
+            await detail_image(reader, writer, message)
+
+        case 'upscale_image':
+            await upscale_image(reader, writer, message)
+
+        case 'detect':
+            await detect(reader, writer, message)
 
         case 'list_models':
             await list_models(writer)
 
+        case 'list_loras':
+            await list_loras(writer)
+
+        case 'set_memory_mode':
+            await set_memory_mode(writer, message)
+
+        case 'reprioritize_job':
+            found = scheduler.reprioritize(message['job'], message['priority'])
+            await send_json(writer, {'found': found})
+
+        case 'remove_job':
+            found = scheduler.remove(message['job'])
+            await send_json(writer, {'found': found})
 
-async def generate_image(writer, message):
+        case task:
+            raise InvalidRequest(f"unknown task: {task}")
+
+
+class Interrupt(Exception):
+    pass
+
+
+class InvalidRequest(Exception):
+    pass
+
+
+async def generate_image(reader, writer, message):
+    (parameters, stages) = await read_parameters(reader, message)
+    seeds = message.get('seeds') or [message.get('seed')]
+
+    def generate(index, on_progress):
+        return text_to_image.generate(parameters=dataclasses.replace(parameters, seed=seeds[index]),
+                                      **stages,
+                                      on_progress=on_progress,
+                                      memory_mode=memory_mode)
+
+    await run_job(reader, writer, message, len(seeds), generate)
+
+
+async def outpaint_image(reader, writer, message):
+    (parameters, _stages) = await read_parameters(reader, message)
+    image = await read_image(reader, message['image'])
+    outpaint = text_to_image.Outpaint.from_dict(message['outpaint'])
+
+    def generate(_index, on_progress):
+        return text_to_image.outpaint(parameters=parameters,
+                                      image=image,
+                                      outpaint=outpaint,
+                                      scale=message['scale'],
+                                      on_progress=on_progress,
+                                      memory_mode=memory_mode)
+
+    await run_job(reader, writer, message, 1, generate)
+
+
+async def detail_image(reader, writer, message):
+    (parameters, _stages) = await read_parameters(reader, message)
+    image = await read_image(reader, message['image'])
+    detector = text_to_image.Detector(message['detector'])
+    detail = text_to_image.Detail.from_dict(message['detail'])
+
+    def generate(_index, on_progress):
+        return text_to_image.refine(parameters=parameters,
+                                    image=image,
+                                    detector=detector,
+                                    detail=detail,
+                                    scale=message['scale'],
+                                    on_progress=on_progress,
+                                    memory_mode=memory_mode)
+
+    await run_job(reader, writer, message, 1, generate)
+
+
+async def upscale_image(reader, writer, message):
+    image = await read_image(reader, message['image'])
+    upscaler = read_upscaler(message['upscaler'])
+
+    def generate(_index, _on_progress):
+        return text_to_image.upscale(image=image, upscaler=upscaler)
+
+    await run_job(reader, writer, message, 1, generate)
+
+
+async def read_parameters(reader, message):
     model = f"/models/{message['model']}.safetensors"
+
+    if not os.path.isfile(model):
+        raise text_to_image.ModelNotFound(message['model'])
     prompt = message['prompt']
     negative_prompt = message['negative_prompt']
     size = message['size']
     quality = message['quality']
     steps = message.get('steps')
+    guidance = message.get('guidance') or 5.0
     seed = message.get('seed')
     inpaints = message.get('inpaints') or []
+    outpaints = message.get('outpaints') or []
     loras = message.get('loras') or []
     sampler = message.get('sampler') or 'euler_a'
-    upscaler = message.get('upscaler')
-    preview_after = message.get('preview_after')
+    upscalers = message.get('upscalers') or []
     face_detail = message.get('face_detail')
     hand_detail = message.get('hand_detail')
-    cpu_offload = message.get('cpu_offload') or False
-
-    if not upscaler is None:
-        upscaling = {
-            '2x-real_esrgan': text_to_image.Upscaling.REAL_ESRGAN_2X,
-            '4x-ultrasharp': text_to_image.Upscaling.ULTRASHARP_4X,
-        }.get(upscaler['model'], text_to_image.Upscaling.ULTRASHARP_4X)
-
-        upscaler = text_to_image.Upscaler(model=upscaling, tile_size=upscaler['tile_size'], tile_padding=upscaler['tile_padding'])
-
-    if preview_after is None:
-        preview_after = 1.0
+    init_image = message.get('init_image')
 
+    upscalers = [read_upscaler(upscaler) for upscaler in upscalers]
 
     if not face_detail is None:
         face_detail = text_to_image.Detail.from_dict(face_detail)
@@ -74,8 +257,26 @@ async def generate_image(writer, message):
     if not hand_detail is None:
         hand_detail = text_to_image.Detail.from_dict(hand_detail)
 
+    if not init_image is None:
+        image = await read_image(reader, init_image)
+        init_image = text_to_image.InitImage(image=image, strength=init_image['strength'])
+
     if inpaints:
-        inpaints = [text_to_image.Inpaint.from_dict(inpaint) for inpaint in inpaints]
+        masks = []
+
+        for inpaint in inpaints:
+            mask = inpaint.get('mask')
+
+            if mask is None:
+                masks.append(None)
+            else:
+                data = await read_bytes(reader)
+                masks.append(Image.frombytes('L', (mask['width'], mask['height']), data))
+
+        inpaints = [text_to_image.Inpaint.from_dict(inpaint, mask) for (inpaint, mask) in zip(inpaints, masks)]
+
+    if outpaints:
+        outpaints = [text_to_image.Outpaint.from_dict(outpaint) for outpaint in outpaints]
 
     if loras:
         loras = [text_to_image.Lora.from_dict(lora) for lora in loras]
@@ -92,114 +293,221 @@ async def generate_image(writer, message):
         case 'insane':
             quality = text_to_image.Quality.INSANE
 
-    match sampler:
-        case 'euler_a':
-            sampler = text_to_image.Sampler.EULER_A
+    if not sampler in SAMPLERS:
+        raise InvalidRequest(f"unknown sampler: {sampler}")
+
+    sampler = SAMPLERS[sampler]
+
+    parameters = text_to_image.Parameters(model=model,
+                                          prompt=prompt,
+                                          width=size['width'],
+                                          height=size['height'],
+                                          quality=quality,
+                                          steps=steps,
+                                          guidance=guidance,
+                                          seed=seed,
+                                          negative_prompt=negative_prompt,
+                                          loras=loras,
+                                          sampler=sampler,
+                                          init_image=init_image)
+
+    stages = {
+        'upscalers': upscalers,
+        'face_detail': face_detail,
+        'hand_detail': hand_detail,
+        'inpaints': inpaints,
+        'outpaints': outpaints,
+    }
+
+    return (parameters, stages)
+
 
-        case 'dpm++_sde_karras':
-            sampler = text_to_image.Sampler.DPM_SDE_KARRAS
+def read_upscaler(upscaler):
+    upscaling = UPSCALERS.get(upscaler['model'], text_to_image.Upscaling.ULTRASHARP_4X)
 
-        case 'dpm++_2m_karras':
-            sampler = text_to_image.Sampler.DPM_2M_KARRAS
+    return text_to_image.Upscaler(model=upscaling, tile_size=upscaler['tile_size'], tile_padding=upscaler['tile_padding'])
 
-        case 'dpm++_2m_sde_karras':
-            sampler = text_to_image.Sampler.DPM_2M_SDE_KARRAS
+
+async def read_image(reader, image):
+    data = await read_bytes(reader)
+    return Image.frombytes('RGBA', (image['width'], image['height']), data)
+
+
+async def run_job(reader, writer, message, count, generate):
+    preview = message.get('preview')
+    encoding = message.get('encoding')
 
     loop = asyncio.get_running_loop()
+    current = 0
 
-    class Interrupt(Exception):
-        pass
+    def on_position(position):
+        write_json(writer, {'queued': {'position': position}})
+
+    job = Job(id=message['job'],
+              priority=message.get('priority') or 0,
+              on_position=on_position)
+
+    async def listen():
+        try:
+            message = await read_json(reader)
+        except asyncio.IncompleteReadError:
+            scheduler.remove(job.id)
+            return
+
+        if message['task'] == 'cancel':
+            print("[kiroshi] Cancelling generation...")
+            scheduler.remove(job.id)
+
+    listener = asyncio.create_task(listen())
 
-    def on_progress(ratio, preview):
-        if writer.is_closing():
+    def on_progress(ratio, image):
+        if job.cancelled.is_set() or writer.is_closing():
             raise Interrupt()
 
-        if ratio <= preview_after:
+        if preview is None or ratio <= preview['after']:
             return
 
-        preview = preview.filter(ImageFilter.GaussianBlur)
-        preview.putalpha(255)
+        image = image.filter(ImageFilter.GaussianBlur)
+        image.putalpha(255)
+
+        (image, format, data) = transfer.encode(image, preview.get('encoding'), preview.get('max_dimension'))
 
         async def send_progress():
             await send_json(
                 writer, {
-                    'width': preview.width,
-                    'height': preview.height,
+                    'width': image.width,
+                    'height': image.height,
+                    'format': format,
+                    'index': current,
                     'progress': ratio,
                     'is_final': False
                 })
 
-            await send(writer, preview.tobytes())
+            await send(writer, data)
 
         asyncio.run_coroutine_threadsafe(send_progress(), loop)
 
-    def generate():
-        parameters = text_to_image.Parameters(model=model,
-                                              prompt=prompt,
-                                              width=size['width'],
-                                              height=size['height'],
-                                              quality=quality,
-                                              steps=steps,
-                                              seed=seed,
-                                              negative_prompt=negative_prompt,
-                                              loras=loras,
-                                              sampler=sampler)
-
-        return text_to_image.generate(parameters=parameters,
-                                      upscaler=upscaler,
-                                      face_detail=face_detail,
-                                      hand_detail=hand_detail,
-                                      inpaints=inpaints,
-                                      on_progress=on_progress,
-                                      cpu_offload=cpu_offload)
+    try:
+        await scheduler.enter(job)
 
-    start = time.time()
-    generation = await asyncio.to_thread(generate)
-    print(f"Generated: {time.time() - start}s")
+        for index in range(count):
+            current = index
 
-    start = time.time()
-    generation.image.putalpha(255)
-    print(f"Added alpha layer: {time.time() - start}s")
+            if job.cancelled.is_set():
+                raise Interrupt()
 
-    start = time.time()
-    await send_json(
-        writer, {
-            'width': generation.image.width,
-            'height': generation.image.height,
-            'faces': generation.faces,
-            'hands': generation.hands,
-            'progress': 1.0,
-            'is_final': True,
-        })
-    await send(writer, generation.image.tobytes())
-    print(f"Sent: {time.time() - start}s")
+            start = time.time()
+            generation = await asyncio.to_thread(generate, index, on_progress)
+            print(f"Generated: {time.time() - start}s")
 
-    writer.close()
-    await writer.wait_closed()
+            # Steps without progress callbacks (e.g. upscaling) cannot be interrupted
+            if job.cancelled.is_set():
+                raise Interrupt()
 
-    gc.collect()
-    torch.cuda.empty_cache()
+            start = time.time()
+            generation.image.putalpha(255)
+            print(f"Added alpha layer: {time.time() - start}s")
 
+            start = time.time()
+            (_, format, data) = await asyncio.to_thread(transfer.encode, generation.image, encoding)
+            print(f"Encoded as {format}: {time.time() - start}s")
 
-async def list_models(writer: asyncio.StreamWriter):
+            start = time.time()
+            await send_json(
+                writer, {
+                    'index': index,
+                    'width': generation.image.width,
+                    'height': generation.image.height,
+                    'format': format,
+                    'faces': generation.faces,
+                    'hands': generation.hands,
+                    'cache': generation.cache,
+                    'progress': 1.0,
+                    'is_final': True,
+                })
+            await send(writer, data)
+            print(f"Sent: {time.time() - start}s")
+    except Interrupt:
+        print("[kiroshi] Generation cancelled")
+
+        try:
+            await send_json(writer, {'cancelled': True})
+        except ConnectionError:
+            pass
+    finally:
+        scheduler.leave(job)
+        listener.cancel()
+
+        gc.collect()
+        torch.cuda.empty_cache()
+
+
+async def detect(reader, writer, message):
+    image = await read_image(reader, message['image'])
+    detector = text_to_image.Detector(message['detector'])
+
+    detections = await asyncio.to_thread(text_to_image.detect, detector, image, message['confidence'])
+    print(f"[kiroshi] {len(detections)} {detector.value}(s) detected")
+
+    await send_json(writer, {
+        'detections': [{
+            'region': [float(point) for point in detection.bbox],
+            'confidence': float(detection.confidence),
+            'mask': None if detection.mask is None else {
+                'width': detection.mask.width,
+                'height': detection.mask.height
+            },
+        } for detection in detections]
+    })
+
+    for detection in detections:
+        if not detection.mask is None:
+            await send(writer, detection.mask.convert('L').tobytes())
+
+
+async def set_memory_mode(writer: Channel, message):
+    global memory_mode
+
+    memory_mode = text_to_image.MemoryMode(message['memory_mode'])
+    print(f"[kiroshi] Memory mode: {memory_mode.value}")
+
+    await send_json(writer, {'memory_mode': memory_mode.value})
+
+
+async def list_models(writer: Channel):
     models = [os.path.splitext(file)[0] for file in os.listdir('/models') if os.path.isfile(f"/models/{file}") and file.endswith('.safetensors')]
 
     await send_json(writer, { 'models': models })
 
 
-async def send_json(writer: asyncio.StreamWriter, data={}):
-    data = json.dumps(data).encode('utf-8')
-    size = len(data)
+async def list_loras(writer: Channel):
+    loras = []
 
-    writer.write(int.to_bytes(size, 8, "big", signed=False))
-    writer.write(data)
+    if os.path.isdir('/loras'):
+        loras = [os.path.splitext(file)[0] for file in os.listdir('/loras') if os.path.isfile(f"/loras/{file}") and file.endswith('.safetensors')]
+
+    await send_json(writer, { 'loras': loras })
+
+
+async def read_json(reader: Channel):
+    message = await read_bytes(reader)
+    return json.loads(message)
+
+
+async def read_bytes(reader: Channel):
+    return await reader.read()
+
+
+async def send_json(writer: Channel, data={}):
+    write_json(writer, data)
     await writer.drain()
 
 
-async def send(writer: asyncio.StreamWriter, data):
-    size = len(data)
+def write_json(writer: Channel, data={}):
+    writer.write(json.dumps(data).encode('utf-8'))
+
 
-    writer.write(int.to_bytes(size, 8, "big", signed=False))
+async def send(writer: Channel, data):
     writer.write(data)
     await writer.drain()
 
@@ -0,0 +1,85 @@
+from dataclasses import dataclass, field
+from typing import Callable
+import asyncio
+import threading
+
+
+@dataclass
+class Job:
+    id: int
+    priority: int
+    on_position: Callable[[int], None]
+    sequence: int = 0
+    position: int | None = None
+    cancelled: threading.Event = field(default_factory=threading.Event)
+    started: asyncio.Event = field(default_factory=asyncio.Event)
+
+
+class Scheduler:
+    def __init__(self):
+        self.waiting: list[Job] = []
+        self.running: Job | None = None
+        self.sequence = 0
+
+    async def enter(self, job: Job):
+        self.sequence += 1
+        job.sequence = self.sequence
+
+        self.waiting.append(job)
+        self.update()
+
+        await job.started.wait()
+
+    def leave(self, job: Job):
+        if self.running is job:
+            self.running = None
+
+        if job in self.waiting:
+            self.waiting.remove(job)
+
+        self.update()
+
+    def reprioritize(self, id: int, priority: int) -> bool:
+        job = self.find(id)
+
+        if job is None:
+            return False
+
+        job.priority = priority
+        self.update()
+
+        return True
+
+    def remove(self, id: int) -> bool:
+        job = self.find(id)
+
+        if job is None:
+            return False
+
+        job.cancelled.set()
+
+        if job in self.waiting:
+            self.waiting.remove(job)
+            job.started.set()
+
+        self.update()
+
+        return True
+
+    def find(self, id: int) -> Job | None:
+        if not self.running is None and self.running.id == id:
+            return self.running
+
+        return next((job for job in self.waiting if job.id == id), None)
+
+    def update(self):
+        self.waiting.sort(key=lambda job: (-job.priority, job.sequence))
+
+        if self.running is None and self.waiting:
+            self.running = self.waiting.pop(0)
+            self.running.started.set()
+
+        for position, job in enumerate(self.waiting):
+            if job.position != position:
+                job.position = position
+                job.on_position(position)
@@ -1,15 +1,16 @@
+from text_to_image.cache import Cache, Entry as CacheEntry, key as cache_key
 from text_to_image.configuration import Configuration
-from text_to_image.detail import Detail, increase_face_detail, increase_hand_detail
+from text_to_image.detail import Detail, Detection, Detector, detect, increase_face_detail, increase_hand_detail
 
 from enum import Enum
 from PIL import Image
 from typing import Callable
-from dataclasses import dataclass, field
+from dataclasses import dataclass, field, replace
 import torch
 import threading
 import gc
 import time
-from pathlib import Path
+import os
 
 class Quality(Enum):
     LOW = 0
@@ -37,38 +38,67 @@ class Upscaling(Enum):
                 return "4x-UltraSharp"
 
 
+class MemoryMode(Enum):
+    GPU = 'gpu'
+    MODEL_OFFLOAD = 'model_offload'
+    SEQUENTIAL_OFFLOAD = 'sequential_offload'
+
+
 class Sampler(Enum):
     EULER_A = 0
     DPM_SDE_KARRAS = 1
     DPM_2M_KARRAS = 2
     DPM_2M_SDE_KARRAS = 3
 
-last_parameters = None
-last_image = None
-last_face = None
-last_hand = None
-last_inpaints = None
+cache = Cache(int(os.environ.get('KIROSHI_CACHE_BUDGET') or 2048) * 1024 * 1024)
 last_model = None
 last_loras = None
 last_sampler = None
-last_cpu_offload = None
+last_memory_mode = None
 pipe = None
 inpainting_pipe = None
+image_to_image_pipe = None
 upscaler_pipe = None
 last_upscaling = None
 semaphore = threading.Semaphore()
 
 
+class ModelNotFound(Exception):
+    def __init__(self, id: str):
+        super().__init__(f"Model not found: {id}")
+        self.id = id
+
+
+class LoraNotFound(Exception):
+    def __init__(self, id: str):
+        super().__init__(f"LoRA not found: {id}")
+        self.id = id
+
+
 @dataclass
 class Lora:
-    path: str
+    id: str
     strength: int
 
     def from_dict(lora: dict):
-        return Lora(path=lora['path'], strength=lora['strength'])
+        lora = Lora(id=lora['id'], strength=lora['strength'])
+
+        if not os.path.isfile(lora.path()):
+            raise LoraNotFound(lora.id)
+
+        return lora
+
+    def path(self):
+        return f"/loras/{self.id}.safetensors"
 
     def name(self):
-        return Path(self.path).stem.replace('.', '')
+        return self.id.replace('.', '')
+
+
+@dataclass
+class InitImage:
+    image: Image
+    strength: int
 
 
 @dataclass
@@ -84,6 +114,7 @@ class Parameters:
     quality: Quality = Quality.NORMAL
     loras: list[Lora] = field(default_factory=list)
     sampler: Sampler = Sampler.EULER_A
+    init_image: InitImage | None = None
 
 
 @dataclass
@@ -93,18 +124,12 @@ class Upscaler:
     tile_padding: int = 24
 
 
-@dataclass
-class Cache:
-    value: any
-    generator: torch.Tensor
-    key: any = None
-
-
 @dataclass
 class Generation:
     image: Image
     faces: list[list[float]]
     hands: list[list[float]]
+    cache: list[dict] = field(default_factory=list)
 
 
 @dataclass
@@ -123,40 +148,371 @@ class Rectangle:
 
 @dataclass
 class Inpaint:
-    region: Rectangle
+    region: Rectangle | None
     strength: int
     padding: int
     prompt: str | None = None
     negative_prompt: str | None = None
+    mask: Image | None = None
+
+    def from_dict(inpaint: dict, mask: Image | None = None):
+        region = inpaint.get('region')
 
-    def from_dict(inpaint: dict):
-        return Inpaint(region=Rectangle.from_dict(inpaint['region']),
+        return Inpaint(region=region and Rectangle.from_dict(region),
+                       mask=mask,
                        prompt=inpaint['prompt'],
                        negative_prompt=inpaint['negative_prompt'],
                        strength=inpaint['strength'],
                        padding=inpaint['padding'])
 
+@dataclass
+class Outpaint:
+    top: int
+    right: int
+    bottom: int
+    left: int
+    prompt: str | None = None
+    negative_prompt: str | None = None
+
+    def from_dict(outpaint: dict):
+        return Outpaint(top=outpaint['top'],
+                        right=outpaint['right'],
+                        bottom=outpaint['bottom'],
+                        left=outpaint['left'],
+                        prompt=outpaint.get('prompt'),
+                        negative_prompt=outpaint.get('negative_prompt'))
+
+
 def generate(parameters: Parameters,
-             upscaler: Upscaler | None = None,
+             upscalers: list[Upscaler] | None = None,
              face_detail: Detail | None = None,
              hand_detail: Detail | None = None,
              inpaints: list[Inpaint] | None = None,
+             outpaints: list[Outpaint] | None = None,
              on_progress: Callable[float, Image] | None = None,
-             cpu_offload: bool = False) -> Generation:
-    global last_parameters, last_image, last_face, last_hand, last_inpaints, last_generator, last_model, last_loras, last_sampler, last_cpu_offload
-    global pipe, inpainting_pipe, last_upscaling, upscaler_pipe, compel_proc, semaphore
+             memory_mode: MemoryMode = MemoryMode.GPU) -> Generation:
     semaphore.acquire()
 
-    from diffusers import AutoPipelineForInpainting, StableDiffusionXLPipeline
+    try:
+        load(parameters, memory_mode)
+
+        (configuration, quality_factor) = configure(parameters, on_progress)
+
+        # Without a seed the generator is random, so nothing can be reused
+        is_cacheable = not parameters.seed is None
+        stages = []
+
+        def lookup(key):
+            if not is_cacheable:
+                return None
+
+            entry = cache.get(key)
+
+            if not entry is None:
+                configuration.generator.set_state(entry.generator)
+
+            return entry
+
+        def store(key, image, detections=[]):
+            if is_cacheable:
+                cache.put(key, CacheEntry(image=image,
+                                          detections=detections,
+                                          generator=configuration.generator.get_state()))
+
+        key = cache_key(parameters)
+        entry = lookup(key)
+        stages.append({'stage': 'image', 'hit': not entry is None})
+
+        if entry is None:
+            if parameters.init_image is None:
+                image = pipe(
+                    num_inference_steps=configuration.steps,
+                    guidance_scale=configuration.guidance,
+                    prompt_embeds=configuration.prompt_embeds,
+                    pooled_prompt_embeds=configuration.prompt_pooled,
+                    negative_prompt_embeds=configuration.negative_prompt_embeds,
+                    negative_pooled_prompt_embeds=configuration.
+                    negative_prompt_pooled,
+                    width=configuration.width,
+                    height=configuration.height,
+                    generator=configuration.generator,
+                    callback_on_step_end=configuration.on_step_end,
+                    callback_on_step_end_tensor_inputs=["latents"],
+                ).images[0]
+            else:
+                init_image = parameters.init_image.image.convert("RGB").resize(
+                    (configuration.width, configuration.height))
+
+                image = image_to_image_pipe(
+                    image=init_image,
+                    strength=parameters.init_image.strength / 100.0,
+                    num_inference_steps=configuration.steps,
+                    guidance_scale=configuration.guidance,
+                    prompt_embeds=configuration.prompt_embeds,
+                    pooled_prompt_embeds=configuration.prompt_pooled,
+                    negative_prompt_embeds=configuration.negative_prompt_embeds,
+                    negative_pooled_prompt_embeds=configuration.
+                    negative_prompt_pooled,
+                    generator=configuration.generator,
+                    callback_on_step_end=configuration.on_step_end,
+                    callback_on_step_end_tensor_inputs=["latents"],
+                ).images[0]
+
+            store(key, image)
+        else:
+            image = entry.image
+
+        faces = []
+        hands = []
+
+        if not face_detail is None:
+            face_detail = face_detail.scale(quality_factor)
+
+            key = cache_key(key, 'face_detail', face_detail)
+            entry = lookup(key)
+            stages.append({'stage': 'face_detail', 'hit': not entry is None})
+
+            if entry is None:
+                (image, faces) = increase_face_detail(face_detail,
+                                                      configuration, image,
+                                                      inpainting_pipe)
+                store(key, image, faces)
+            else:
+                (image, faces) = (entry.image, entry.detections)
+
+        if not hand_detail is None:
+            hand_detail = hand_detail.scale(quality_factor)
+
+            key = cache_key(key, 'hand_detail', hand_detail)
+            entry = lookup(key)
+            stages.append({'stage': 'hand_detail', 'hit': not entry is None})
+
+            if entry is None:
+                (image, hands) = increase_hand_detail(hand_detail, configuration,
+                                                      image, inpainting_pipe)
+                store(key, image, hands)
+            else:
+                (image, hands) = (entry.image, entry.detections)
+
+        for i, inpaint in enumerate(inpaints or []):
+            key = cache_key(key, 'inpaint', inpaint)
+            entry = lookup(key)
+            stages.append({'stage': 'inpaint', 'index': i, 'hit': not entry is None})
+
+            if not entry is None:
+                image = entry.image
+                continue
+
+            prompt_embeds = configuration.prompt_embeds
+            prompt_pooled = configuration.prompt_pooled
+            negative_prompt_embeds = configuration.negative_prompt_embeds
+            negative_prompt_pooled = configuration.negative_prompt_pooled
+
+            if not inpaint.prompt is None or not inpaint.negative_prompt is None:
+                prompt_embeds, prompt_pooled = compel_proc(inpaint.prompt or parameters.prompt)
+                negative_prompt_embeds, negative_prompt_pooled = compel_proc(inpaint.negative_prompt or parameters.negative_prompt)
+
+                [prompt_embeds, negative_prompt_embeds
+                 ] = compel_proc.pad_conditioning_tensors_to_same_length(
+                     [prompt_embeds, negative_prompt_embeds])
+
+            from adetailer.common import create_mask_from_bbox
+            from adetailer.mask import mask_preprocess, bbox_area
+
+            if inpaint.mask is None:
+                mask = create_mask_from_bbox([[
+                    inpaint.region.x * image.width,
+                    inpaint.region.y * image.height,
+                    (inpaint.region.x + inpaint.region.width) * image.width,
+                    (inpaint.region.y + inpaint.region.height) * image.height]],
+                    image.size,
+                )[0]
+            else:
+                mask = inpaint.mask.resize(image.size)
+
+            mask = mask_preprocess([mask], 4)[0]
+            mask = inpainting_pipe.mask_processor.blur(mask, blur_factor=inpaint.padding / 4)
+
+            image = inpainting_pipe(
+                image=image,
+                mask_image=mask,
+                strength=inpaint.strength / 100.0,
+                padding_mask_crop=inpaint.padding,
+                num_inference_steps=configuration.steps,
+                guidance_scale=configuration.guidance,
+                prompt_embeds=prompt_embeds,
+                pooled_prompt_embeds=prompt_pooled,
+                negative_prompt_embeds=negative_prompt_embeds,
+                negative_pooled_prompt_embeds=negative_prompt_pooled,
+                width=configuration.width,
+                height=configuration.height,
+                generator=configuration.generator,
+                callback_on_step_end=configuration.on_step_end,
+                callback_on_step_end_tensor_inputs=["latents"],
+            ).images[0]
+
+            store(key, image)
+
+        # Outpaints are not cached, so they always run and are not reported as stages
+        for outpaint in outpaints or []:
+            image = extend(image, outpaint, quality_factor, parameters, configuration)
+
+        if not upscalers:
+            image = image.copy()
+        else:
+            on_progress(1.0, image.copy())
+
+        # Passes run in the order they were applied to the original image
+        for upscaler in upscalers or []:
+            print(f"Upscaling: {upscaler}")
+            load_upscaler(upscaler)
+
+            start = time.time()
+            image = upscaler_pipe.predict(image, patches_size=upscaler.tile_size, padding=upscaler.tile_padding)
+
+            scale = upscaler.model.scale()
+            faces = [[point * scale for point in face] for face in faces]
+            hands = [[point * scale for point in hand] for hand in hands]
+            print(f"Upscaled: {time.time() - start}s")
+
+    finally:
+        semaphore.release()
+
+    return Generation(image, faces, hands, stages)
+
+
+def outpaint(parameters: Parameters,
+             image: Image,
+             outpaint: Outpaint,
+             scale: float,
+             on_progress: Callable[float, Image] | None = None,
+             memory_mode: MemoryMode = MemoryMode.GPU) -> Generation:
+    with semaphore:
+        load(parameters, memory_mode)
+
+        (configuration, _) = configure(parameters, on_progress)
+        image = extend(image.convert("RGB"), outpaint, scale, parameters, configuration)
+
+    return Generation(image, [], [])
+
+
+def refine(parameters: Parameters,
+           image: Image,
+           detector: Detector,
+           detail: Detail,
+           scale: float,
+           on_progress: Callable[float, Image] | None = None,
+           memory_mode: MemoryMode = MemoryMode.GPU) -> Generation:
+    with semaphore:
+        load(parameters, memory_mode)
+
+        (configuration, _) = configure(parameters, on_progress)
+        configuration = replace(configuration, width=image.width, height=image.height)
+
+        image = image.convert("RGB")
+        detail = detail.scale(scale)
+
+        match detector:
+            case Detector.FACE:
+                (image, faces) = increase_face_detail(detail, configuration, image, inpainting_pipe)
+                return Generation(image, faces, [])
+
+            case Detector.HAND:
+                (image, hands) = increase_hand_detail(detail, configuration, image, inpainting_pipe)
+                return Generation(image, [], hands)
+
+
+def upscale(image: Image, upscaler: Upscaler) -> Generation:
+    with semaphore:
+        load_upscaler(upscaler)
+
+        print(f"Upscaling: {upscaler}")
+
+        start = time.time()
+        image = upscaler_pipe.predict(image.convert("RGB"), patches_size=upscaler.tile_size, padding=upscaler.tile_padding)
+        print(f"Upscaled: {time.time() - start}s")
+
+    return Generation(image, [], [])
+
+
+def extend(image: Image, outpaint: Outpaint, scale: float,
+           parameters: Parameters, configuration: Configuration) -> Image:
+    from PIL import ImageFilter
+
+    [top, right, bottom, left] = [
+        int(side * scale) for side in
+        [outpaint.top, outpaint.right, outpaint.bottom, outpaint.left]
+    ]
+
+    width = image.width + left + right
+    height = image.height + top + bottom
+
+    # The pipeline only works with multiples of 8
+    canvas_width = (width + 7) // 8 * 8
+    canvas_height = (height + 7) // 8 * 8
+
+    canvas = image.resize((canvas_width, canvas_height)).filter(
+        ImageFilter.GaussianBlur(32))
+    canvas.paste(image, (left, top))
+
+    # Overlap the original image slightly on extended sides to blend the seams
+    overlap = int(8 * scale)
+
+    mask = Image.new("L", (canvas_width, canvas_height), 255)
+    mask.paste(0, (
+        left + (overlap if left > 0 else 0),
+        top + (overlap if top > 0 else 0),
+        left + image.width - (overlap if right > 0 else 0),
+        top + image.height - (overlap if bottom > 0 else 0),
+    ))
+    mask = inpainting_pipe.mask_processor.blur(mask, blur_factor=overlap / 2)
+
+    prompt_embeds = configuration.prompt_embeds
+    prompt_pooled = configuration.prompt_pooled
+    negative_prompt_embeds = configuration.negative_prompt_embeds
+    negative_prompt_pooled = configuration.negative_prompt_pooled
+
+    if not outpaint.prompt is None or not outpaint.negative_prompt is None:
+        prompt_embeds, prompt_pooled = compel_proc(outpaint.prompt or parameters.prompt)
+        negative_prompt_embeds, negative_prompt_pooled = compel_proc(outpaint.negative_prompt or parameters.negative_prompt)
+
+        [prompt_embeds, negative_prompt_embeds
+         ] = compel_proc.pad_conditioning_tensors_to_same_length(
+             [prompt_embeds, negative_prompt_embeds])
+
+    image = inpainting_pipe(
+        image=canvas,
+        mask_image=mask,
+        strength=1.0,
+        num_inference_steps=configuration.steps,
+        guidance_scale=configuration.guidance,
+        prompt_embeds=prompt_embeds,
+        pooled_prompt_embeds=prompt_pooled,
+        negative_prompt_embeds=negative_prompt_embeds,
+        negative_pooled_prompt_embeds=negative_prompt_pooled,
+        width=canvas_width,
+        height=canvas_height,
+        generator=configuration.generator,
+        callback_on_step_end=configuration.on_step_end,
+        callback_on_step_end_tensor_inputs=["latents"],
+    ).images[0]
+
+    return image.crop((0, 0, width, height))
+
+
+def load(parameters: Parameters, memory_mode: MemoryMode):
+    global last_model, last_loras, last_sampler, last_memory_mode
+    global pipe, inpainting_pipe, image_to_image_pipe, compel_proc
+
+    from diffusers import AutoPipelineForImage2Image, AutoPipelineForInpainting, StableDiffusionXLPipeline
     from compel import Compel, ReturnedEmbeddingsType
-    from RealESRGAN import RealESRGAN
     import torch
 
     if parameters.loras != last_loras:
         last_model = None
 
-    if last_model != parameters.model or last_cpu_offload != cpu_offload:
-        del pipe, inpainting_pipe
+    if last_model != parameters.model or last_memory_mode != memory_mode:
+        del pipe, inpainting_pipe, image_to_image_pipe
         gc.collect()
         torch.cuda.empty_cache()
 
@@ -166,15 +522,12 @@ def generate(parameters: Parameters,
             use_safetensors=True,
             torch_dtype=torch.float16,
             local_files_only=True)
-        pipe = pipe.to("cuda")
         pipe.safety_checker = None
 
         last_model = parameters.model
         last_loras = parameters.loras
         last_sampler = None
-        last_image = None
-        last_face = None
-        last_cpu_offload = cpu_offload
+        last_memory_mode = memory_mode
 
         if parameters.loras:
             print("Fusing LoRAs...")
@@ -182,7 +535,7 @@ def generate(parameters: Parameters,
 
             for lora in parameters.loras:
                 pipe.load_lora_weights(
-                    lora.path,
+                    lora.path(),
                     adapter_name=lora.name(),
                 )
 
@@ -213,8 +566,15 @@ def generate(parameters: Parameters,
         #                           mode="reduce-overhead",
         #                           fullgraph=True)
 
-        if cpu_offload:
-            pipe.enable_model_cpu_offload()
+        match memory_mode:
+            case MemoryMode.GPU:
+                # Offloading moves the weights itself and fails on a pipe
+                # that was already moved
+                pipe = pipe.to("cuda")
+            case MemoryMode.MODEL_OFFLOAD:
+                pipe.enable_model_cpu_offload()
+            case MemoryMode.SEQUENTIAL_OFFLOAD:
+                pipe.enable_sequential_cpu_offload()
 
     if last_sampler != parameters.sampler:
         match parameters.sampler:
@@ -258,8 +618,15 @@ def generate(parameters: Parameters,
                 )
 
         inpainting_pipe = AutoPipelineForInpainting.from_pipe(pipe)
+        image_to_image_pipe = AutoPipelineForImage2Image.from_pipe(pipe)
         last_sampler = parameters.sampler
 
+
+def load_upscaler(upscaler: Upscaler | None):
+    global last_upscaling, upscaler_pipe
+
+    from RealESRGAN import RealESRGAN
+
     if not upscaler is None and last_upscaling != upscaler.model:
         weight = upscaler.model.weight()
         scale = upscaler.model.scale()
@@ -271,6 +638,9 @@ def generate(parameters: Parameters,
         upscaler_pipe.load_weights(f'weights/{weight}.pth')
         last_upscaling = upscaler.model
 
+
+def configure(parameters: Parameters,
+              on_progress: Callable[float, Image]) -> (Configuration, float):
     def on_step_end(pipe, step, timestep, callback_kwargs):
         nonlocal on_progress
         latents = callback_kwargs["latents"]
@@ -322,166 +692,7 @@ def generate(parameters: Parameters,
         generator=generator,
         on_step_end=on_step_end)
 
-    is_new = last_image is None or parameters.seed is None or parameters != last_parameters
-
-    try:
-        if is_new:
-            image = pipe(
-                num_inference_steps=configuration.steps,
-                guidance_scale=configuration.guidance,
-                prompt_embeds=configuration.prompt_embeds,
-                pooled_prompt_embeds=configuration.prompt_pooled,
-                negative_prompt_embeds=configuration.negative_prompt_embeds,
-                negative_pooled_prompt_embeds=configuration.
-                negative_prompt_pooled,
-                width=configuration.width,
-                height=configuration.height,
-                generator=configuration.generator,
-                callback_on_step_end=configuration.on_step_end,
-                callback_on_step_end_tensor_inputs=["latents"],
-            ).images[0]
-
-            last_face = None
-            last_hand = None
-            last_inpaints = None
-
-            if parameters.seed is None:
-                last_parameters = None
-                last_image = None
-            else:
-                last_parameters = parameters
-                last_image = Cache(
-                    value=image, generator=configuration.generator.get_state())
-        else:
-            image = last_image.value
-            configuration.generator.set_state(last_image.generator)
-
-        faces = []
-        hands = []
-
-        if not face_detail is None:
-            face_detail.padding *= quality_factor
-
-            if not face_detail.max_area is None:
-                face_detail.max_area *= quality_factor
-
-            if last_face is None or face_detail != last_face.key:
-                (image, faces) = increase_face_detail(face_detail,
-                                                      configuration, image,
-                                                      inpainting_pipe)
-
-                last_hand = None
-                last_inpaints = None
-
-                if parameters.seed is None:
-                    last_face = None
-                else:
-                    last_face = Cache(
-                        key=face_detail,
-                        value=(image, faces),
-                        generator=configuration.generator.get_state())
-            else:
-                (image, faces) = last_face.value
-                configuration.generator.set_state(last_face.generator)
-
-        if not hand_detail is None:
-            hand_detail.padding *= quality_factor
-
-            if not hand_detail.max_area is None:
-                hand_detail.max_area *= quality_factor
-
-            if last_hand is None or hand_detail != last_hand.key:
-                (image, hands) = increase_hand_detail(hand_detail, configuration,
-                                                      image, inpainting_pipe)
-
-                last_inpaints = None
-
-                if parameters.seed is None:
-                    last_hand = None
-                else:
-                    last_hand = Cache(
-                        key=hand_detail,
-                        value=(image, hands),
-                        generator=configuration.generator.get_state())
-            else:
-                (image, hands) = last_hand.value
-                configuration.generator.set_state(last_hand.generator)
-
-
-        for i, inpaint in enumerate(inpaints):
-            if not last_inpaints is None and i < len(last_inpaints.key) and last_inpaints.key[i] == inpaint:
-                image = last_inpaints.value[i]
-                configuration.generator.set_state(last_inpaints.generator[i])
-                continue
-
-            prompt_embeds = configuration.prompt_embeds
-            prompt_pooled = configuration.prompt_pooled
-            negative_prompt_embeds = configuration.negative_prompt_embeds
-            negative_prompt_pooled = configuration.negative_prompt_pooled
-
-            if not inpaint.prompt is None or not inpaint.negative_prompt is None:
-                prompt_embeds, prompt_pooled = compel_proc(inpaint.prompt or parameters.prompt)
-                negative_prompt_embeds, negative_prompt_pooled = compel_proc(inpaint.negative_prompt or parameters.negative_prompt)
-
-                [prompt_embeds, negative_prompt_embeds
-                 ] = compel_proc.pad_conditioning_tensors_to_same_length(
-                     [prompt_embeds, negative_prompt_embeds])
-
-            from adetailer.common import create_mask_from_bbox
-            from adetailer.mask import mask_preprocess, bbox_area
-
-            mask = create_mask_from_bbox([[
-                inpaint.region.x * image.width,
-                inpaint.region.y * image.height,
-                (inpaint.region.x + inpaint.region.width) * image.width,
-                (inpaint.region.y + inpaint.region.height) * image.height]],
-                image.size,
-            )[0]
-
-            mask = mask_preprocess([mask], 4)[0]
-            mask = inpainting_pipe.mask_processor.blur(mask, blur_factor=inpaint.padding / 4)
-
-            image = inpainting_pipe(
-                image=image,
-                mask_image=mask,
-                strength=inpaint.strength / 100.0,
-                padding_mask_crop=inpaint.padding,
-                num_inference_steps=configuration.steps,
-                guidance_scale=configuration.guidance,
-                prompt_embeds=prompt_embeds,
-                pooled_prompt_embeds=prompt_pooled,
-                negative_prompt_embeds=negative_prompt_embeds,
-                negative_pooled_prompt_embeds=negative_prompt_pooled,
-                width=configuration.width,
-                height=configuration.height,
-                generator=configuration.generator,
-                callback_on_step_end=configuration.on_step_end,
-                callback_on_step_end_tensor_inputs=["latents"],
-            ).images[0]
-
-            last_inpaints = Cache(
-                key=(last_inpaints and last_inpaints.key[:i] or []) + [inpaint],
-                value=(last_inpaints and last_inpaints.value[:i] or []) + [image],
-                generator=(last_inpaints and last_inpaints.generator[:i] or []) + [configuration.generator.get_state()])
-
-        if upscaler is None:
-            image = image.copy()
-        else:
-            print(f"Upscaling: {upscaler}")
-            on_progress(1.0, image.copy())
-
-            start = time.time()
-            image = upscaler_pipe.predict(image, patches_size=upscaler.tile_size, padding=upscaler.tile_padding)
-
-            scale = upscaler.model.scale()
-            faces = [[point * scale for point in face] for face in faces]
-            hands = [[point * scale for point in hand] for hand in hands]
-            print(f"Upscaled: {time.time() - start}s")
-
-    finally:
-        semaphore.release()
-
-    return Generation(image, faces, hands)
+    return (configuration, quality_factor)
 
 
 def latents_to_rgb(latents):
@@ -0,0 +1,80 @@
+from collections import OrderedDict
+from dataclasses import dataclass, fields, is_dataclass
+from enum import Enum
+from PIL import Image
+import hashlib
+
+
+@dataclass
+class Entry:
+    image: Image
+    detections: list[list[float]]
+    generator: any
+
+    def size(self):
+        return self.image.width * self.image.height * len(self.image.getbands())
+
+
+class Cache:
+    def __init__(self, budget: int):
+        self.budget = budget
+        self.entries = OrderedDict()
+        self.used = 0
+
+    def get(self, key: str) -> Entry | None:
+        entry = self.entries.get(key)
+
+        if not entry is None:
+            self.entries.move_to_end(key)
+
+        return entry
+
+    def put(self, key: str, entry: Entry):
+        if key in self.entries:
+            self.used -= self.entries.pop(key).size()
+
+        if entry.size() > self.budget:
+            return
+
+        self.entries[key] = entry
+        self.used += entry.size()
+
+        while self.used > self.budget:
+            (_, evicted) = self.entries.popitem(last=False)
+            self.used -= evicted.size()
+
+    def clear(self):
+        self.entries.clear()
+        self.used = 0
+
+
+def key(*parts) -> str:
+    hasher = hashlib.sha256()
+
+    for part in parts:
+        update(hasher, part)
+
+    return hasher.hexdigest()
+
+
+def update(hasher, value):
+    hasher.update(type(value).__name__.encode('utf-8'))
+
+    if value is None or isinstance(value, (bool, int, float, str)):
+        hasher.update(repr(value).encode('utf-8'))
+    elif isinstance(value, Enum):
+        update(hasher, value.value)
+    elif isinstance(value, Image.Image):
+        update(hasher, [value.mode, value.width, value.height])
+        hasher.update(value.tobytes())
+    elif is_dataclass(value):
+        for field in fields(value):
+            update(hasher, field.name)
+            update(hasher, getattr(value, field.name))
+    elif isinstance(value, (list, tuple)):
+        update(hasher, len(value))
+
+        for item in value:
+            update(hasher, item)
+    else:
+        raise TypeError(f"Unhashable cache key: {type(value).__name__}")
@@ -2,7 +2,9 @@ from text_to_image.configuration import Configuration
 
 from multiprocessing import Process, Queue
 from dataclasses import dataclass
+from enum import Enum
 from PIL import Image
+import threading
 import torch
 import gc
 
@@ -20,11 +22,35 @@ class Detail:
                       padding=detail['padding'],
                       max_area=detail.get('max_area'))
 
+    def scale(self, factor: float):
+        return Detail(strength=self.strength,
+                      padding=self.padding * factor,
+                      max_area=None if self.max_area is None else self.max_area * factor)
+
+
+class Detector(Enum):
+    FACE = 'face'
+    HAND = 'hand'
+
+    def model(self):
+        match self:
+            case Detector.FACE:
+                return "weights/face_yolov8n.pt"
+            case Detector.HAND:
+                return "weights/hand_yolov9c.pt"
+
+
+@dataclass
+class Detection:
+    bbox: list[float]
+    confidence: float
+    mask: Image | None
+
 
 def adetailer(input, output):
     while True:
         try:
-            (model, image) = input.get()
+            (model, image, confidence) = input.get()
         except:
             return
 
@@ -33,21 +59,37 @@ def adetailer(input, output):
         prediction = ultralytics_predict(
             model,
             image,
-            confidence=0.1,
+            confidence=confidence,
         )
         output.put(prediction)
 
 
 AdetailerInput = Queue()
 AdetailerOutput = Queue()
+AdetailerLock = threading.Lock()
 Process(target=adetailer, args=(AdetailerInput, AdetailerOutput)).start()
 
 
+def predict(model: str, image: Image, confidence: float = 0.1):
+    with AdetailerLock:
+        AdetailerInput.put((model, image, confidence))
+        return AdetailerOutput.get()
+
+
+def detect(detector: Detector, image: Image, confidence: float) -> list[Detection]:
+    prediction = predict(detector.model(), image.convert("RGB"), confidence)
+
+    return [
+        Detection(bbox=bbox, confidence=score, mask=mask)
+        for (bbox, score, mask) in zip(prediction.bboxes, prediction.confidences, prediction.masks or [None] * len(prediction.bboxes))
+    ]
+
+
 def increase_face_detail(detail: Detail, configuration: Configuration,
                          image: Image,
                          inpainting) -> (Image, list[list[float]]):
     return increase_detail("face",
-                           "weights/face_yolov8n.pt",
+                           Detector.FACE.model(),
                            detail,
                            configuration,
                            image,
@@ -59,7 +101,7 @@ def increase_hand_detail(detail: Detail, configuration: Configuration,
                          image: Image,
                          inpainting) -> (Image, list[list[float]]):
     return increase_detail("hand",
-                           "weights/hand_yolov9c.pt",
+                           Detector.HAND.model(),
                            detail,
                            configuration,
                            image,
@@ -84,8 +126,7 @@ def increase_detail(
         torch.cuda.empty_cache()
         initialized = True
 
-    AdetailerInput.put((model, image))
-    prediction = AdetailerOutput.get()
+    prediction = predict(model, image)
 
     if not (prediction.masks):
         return (image, [])
@@ -0,0 +1,41 @@
+import io
+from PIL import Image, features
+
+FORMATS = ['raw', 'png', 'jpeg'] + (['webp'] if features.check('webp') else [])
+
+DEFAULT_QUALITY = 85
+
+
+def encode(image: Image.Image, encoding=None, max_dimension=None):
+    encoding = encoding or {'format': 'raw'}
+    format = encoding.get('format', 'raw')
+
+    if not format in FORMATS:
+        raise ValueError(f"unsupported image format: {format}")
+
+    if max_dimension and max(image.width, image.height) > max_dimension:
+        image = image.copy()
+        image.thumbnail((max_dimension, max_dimension), Image.Resampling.BILINEAR)
+
+    quality = max(1, min(100, encoding.get('quality') or DEFAULT_QUALITY))
+
+    match format:
+        case 'raw':
+            return (image, format, image.tobytes())
+
+        case 'png':
+            # Frames are streamed, so favor speed over size
+            return (image, format, save(image, 'PNG', compress_level=1))
+
+        case 'jpeg':
+            return (image, format, save(image.convert('RGB'), 'JPEG', quality=quality))
+
+        case 'webp':
+            return (image, format, save(image, 'WEBP', quality=quality))
+
+
+def save(image: Image.Image, format, **options):
+    buffer = io.BytesIO()
+    image.save(buffer, format, **options)
+
+    return buffer.getvalue()
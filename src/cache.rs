@@ -0,0 +1,31 @@
+use serde::Deserialize;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Lookup {
+    #[serde(flatten)]
+    pub stage: Stage,
+    #[serde(rename = "hit")]
+    pub is_hit: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Stage {
+    Image,
+    FaceDetail,
+    HandDetail,
+    Inpaint { index: usize },
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Image => f.write_str("Image"),
+            Self::FaceDetail => f.write_str("Face detail"),
+            Self::HandDetail => f.write_str("Hand detail"),
+            Self::Inpaint { index } => write!(f, "Inpaint #{}", index + 1),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::Error;
+use crate::server::{Connection, Endpoint};
+use crate::stream::StreamExt;
+
+use bytes::Bytes;
+use futures::channel::mpsc;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+
+use std::collections::HashMap;
+use std::sync::atomic::{self, AtomicBool, AtomicU64};
+use std::sync::{Arc, Mutex};
+
+// A frame with this length closes the channel instead of carrying a payload
+const CLOSE: u64 = u64::MAX;
+
+// Multiplexed connections start with this preamble; anything else is read by the
+// server as a single length-prefixed request, which every protocol version understands
+const PREAMBLE: &[u8; 8] = b"KIROSHI\x01";
+
+const MAX_FRAME_LENGTH: u64 = 1 << 30;
+
+type Routes = Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<Bytes>>>>;
+
+#[derive(Debug)]
+pub(crate) struct Link {
+    outgoing: mpsc::UnboundedSender<Frame>,
+    routes: Routes,
+    next_id: AtomicU64,
+    is_alive: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct Frame {
+    id: u64,
+    payload: Option<Bytes>,
+}
+
+impl Link {
+    pub async fn connect(endpoint: &Endpoint) -> Result<Arc<Self>, Error> {
+        let mut connection = Connection::open(endpoint).await?;
+        connection.write_all(PREAMBLE).await?;
+
+        let (reader, writer) = io::split(connection);
+        let (outgoing, frames) = mpsc::unbounded();
+        let routes = Routes::default();
+        let is_alive = Arc::new(AtomicBool::new(true));
+
+        drop(tokio::spawn(write(writer, frames, is_alive.clone())));
+        drop(tokio::spawn(read(reader, routes.clone(), is_alive.clone())));
+
+        Ok(Arc::new(Self {
+            outgoing,
+            routes,
+            next_id: AtomicU64::new(0),
+            is_alive,
+        }))
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.is_alive.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn channel(self: &Arc<Self>) -> (Sender, Receiver) {
+        let id = self.next_id.fetch_add(1, atomic::Ordering::Relaxed);
+        let (sender, incoming) = mpsc::unbounded();
+
+        if let Ok(mut routes) = self.routes.lock() {
+            routes.insert(id, sender);
+        }
+
+        let route = Arc::new(Route {
+            id,
+            link: self.clone(),
+        });
+
+        (
+            Sender {
+                route: route.clone(),
+            },
+            Receiver {
+                incoming,
+                _route: route,
+            },
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Route {
+    id: u64,
+    link: Arc<Link>,
+}
+
+impl Drop for Route {
+    fn drop(&mut self) {
+        if let Ok(mut routes) = self.link.routes.lock() {
+            routes.remove(&self.id);
+        }
+
+        let _ = self.link.outgoing.unbounded_send(Frame {
+            id: self.id,
+            payload: None,
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Sender {
+    route: Arc<Route>,
+}
+
+impl Sender {
+    pub fn send(&self, payload: Bytes) -> Result<(), Error> {
+        self.route
+            .link
+            .outgoing
+            .unbounded_send(Frame {
+                id: self.route.id,
+                payload: Some(payload),
+            })
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe).into())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Receiver {
+    incoming: mpsc::UnboundedReceiver<Bytes>,
+    _route: Arc<Route>,
+}
+
+impl Receiver {
+    pub async fn receive(&mut self) -> Result<Bytes, Error> {
+        self.incoming
+            .next()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+}
+
+async fn write(
+    writer: impl io::AsyncWrite + Unpin,
+    mut frames: mpsc::UnboundedReceiver<Frame>,
+    is_alive: Arc<AtomicBool>,
+) {
+    let mut writer = io::BufWriter::new(writer);
+
+    while let Some(frame) = frames.next().await {
+        let result = async {
+            writer.write_u64(frame.id).await?;
+
+            match &frame.payload {
+                Some(payload) => {
+                    writer.write_u64(payload.len() as u64).await?;
+                    writer.write_all(payload).await?;
+                }
+                None => {
+                    writer.write_u64(CLOSE).await?;
+                }
+            }
+
+            writer.flush().await
+        }
+        .await;
+
+        if result.is_err() {
+            is_alive.store(false, atomic::Ordering::Relaxed);
+            return;
+        }
+    }
+
+    let _ = writer.shutdown().await;
+}
+
+// Sends a single request over its own connection, without multiplexing
+pub(crate) async fn request(endpoint: &Endpoint, payload: &[u8]) -> Result<Bytes, Error> {
+    let mut connection = Connection::open(endpoint).await?;
+
+    connection.write_u64(payload.len() as u64).await?;
+    connection.write_all(payload).await?;
+    connection.flush().await?;
+
+    let length = connection.read_u64().await?;
+
+    Ok(read_payload(&mut connection, length).await?)
+}
+
+async fn read(reader: impl io::AsyncRead + Unpin, routes: Routes, is_alive: Arc<AtomicBool>) {
+    struct Shutdown {
+        routes: Routes,
+        is_alive: Arc<AtomicBool>,
+    }
+
+    // The link must be marked dead even if routing panics
+    impl Drop for Shutdown {
+        fn drop(&mut self) {
+            self.is_alive.store(false, atomic::Ordering::Relaxed);
+
+            // Dropping the routes ends every pending channel
+            if let Ok(mut routes) = self.routes.lock() {
+                routes.clear();
+            }
+        }
+    }
+
+    let _shutdown = Shutdown {
+        routes: routes.clone(),
+        is_alive,
+    };
+
+    let _ = route(io::BufReader::new(reader), &routes).await;
+}
+
+async fn route(mut reader: impl io::AsyncRead + Unpin, routes: &Routes) -> io::Result<()> {
+    loop {
+        let id = reader.read_u64().await?;
+        let length = reader.read_u64().await?;
+
+        if length == CLOSE {
+            if let Ok(mut routes) = routes.lock() {
+                routes.remove(&id);
+            }
+
+            continue;
+        }
+
+        let payload = read_payload(&mut reader, length).await?;
+
+        if let Some(route) = routes
+            .lock()
+            .ok()
+            .and_then(|routes| routes.get(&id).cloned())
+        {
+            let _ = route.unbounded_send(payload);
+        }
+    }
+}
+
+async fn read_payload(reader: &mut (impl io::AsyncRead + Unpin), length: u64) -> io::Result<Bytes> {
+    if length > MAX_FRAME_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {length} bytes exceeds the limit of {MAX_FRAME_LENGTH}"),
+        ));
+    }
+
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Bytes::from(payload))
+}
@@ -1,4 +1,6 @@
-use crate::{Padding, Strength};
+use crate::image::{self, Definition, Generation, GenerationHandle, Parameters};
+use crate::stream::{Stream, StreamExt};
+use crate::{Detector, Error, Image, Job, Padding, Priority, Server, Size, Strength, Transfer};
 
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +11,74 @@ pub struct Detail {
     pub max_area: Option<Area>,
 }
 
+impl Detail {
+    pub fn apply(
+        self,
+        server: &Server,
+        image: &Image,
+        detector: Detector,
+        definition: &Definition,
+        transfer: Transfer,
+        priority: Priority,
+    ) -> (
+        GenerationHandle,
+        impl Stream<Item = Result<Generation, Error>> + use<>,
+    ) {
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            job: Job,
+            priority: Priority,
+            #[serde(flatten)]
+            parameters: Parameters,
+            image: Size,
+            detector: Detector,
+            detail: Detail,
+            scale: f32,
+            #[serde(flatten)]
+            transfer: Transfer,
+        }
+
+        let job = Job::new();
+
+        let request = Request {
+            task: "detail_image",
+            job,
+            priority,
+            parameters: Parameters::base(definition),
+            image: image.size,
+            detector,
+            detail: self,
+            scale: definition.scale(image.size),
+            transfer: transfer.negotiate(server.handshake()),
+        };
+
+        let definition = match detector {
+            Detector::Face => Definition {
+                face_detail: Some(self),
+                ..definition.clone()
+            },
+            Detector::Hand => Definition {
+                hand_detail: Some(self),
+                ..definition.clone()
+            },
+        };
+
+        let (handle, generation) = image::run(
+            server,
+            job,
+            request,
+            vec![image.rgba.clone()],
+            vec![definition],
+        );
+
+        (
+            handle,
+            generation.map(|generation| generation.map(|(_index, generation)| generation)),
+        )
+    }
+}
+
 impl Default for Detail {
     fn default() -> Self {
         Self {
@@ -0,0 +1,103 @@
+use crate::server::{self, Server};
+use crate::{Error, Image, Mask, Rectangle, Size};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub region: Rectangle,
+    pub confidence: f32,
+    pub mask: Option<Mask>,
+}
+
+impl Detection {
+    pub const DEFAULT_CONFIDENCE: f32 = 0.3;
+
+    pub async fn detect(
+        server: &Server,
+        image: &Image,
+        detector: Detector,
+        confidence: f32,
+    ) -> Result<Vec<Self>, Error> {
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            detector: Detector,
+            confidence: f32,
+            image: Size,
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            detections: Vec<Item>,
+        }
+
+        #[derive(Deserialize)]
+        struct Item {
+            region: [f32; 4],
+            confidence: f32,
+            mask: Option<Size>,
+        }
+
+        let (sender, mut receiver) = server.open().await?;
+
+        server::send_json(
+            &sender,
+            Request {
+                task: "detect",
+                detector,
+                confidence: confidence.clamp(0.0, 1.0),
+                image: image.size,
+            },
+        )?;
+
+        server::send_bytes(&sender, image.rgba.clone())?;
+
+        let Response { detections } = server::read_json(&mut receiver).await?;
+
+        let mut results = Vec::with_capacity(detections.len());
+
+        for detection in detections {
+            let mask = match detection.mask {
+                Some(size) => {
+                    let luma = server::read_bytes(&mut receiver).await?;
+
+                    Some(Mask::new(size, luma).ok_or_else(|| {
+                        Error::InvalidOutput(format!("invalid detection mask: {size:?}"))
+                    })?)
+                }
+                None => None,
+            };
+
+            results.push(Self {
+                region: Rectangle::from_array(detection.region),
+                confidence: detection.confidence,
+                mask,
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detector {
+    Face,
+    Hand,
+}
+
+impl Detector {
+    pub const ALL: &'static [Self] = &[Self::Face, Self::Hand];
+}
+
+impl fmt::Display for Detector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Face => "Face",
+            Self::Hand => "Hand",
+        })
+    }
+}
@@ -1,3 +1,6 @@
+use crate::Model;
+use crate::lora::LoraId;
+
 use std::io;
 use std::sync::Arc;
 
@@ -7,8 +10,38 @@ pub enum Error {
     IOFailed(Arc<io::Error>),
     #[error("serialization failed: {0}")]
     SerializationFailed(Arc<serde_json::Error>),
+    #[error("toml serialization failed: {0}")]
+    TomlFailed(String),
+    #[error("unsupported project version: {}", describe_version(*.version))]
+    UnsupportedProject { version: Option<u64> },
     #[error("docker operation failed")]
     DockerFailed,
+    #[error("operation timed out")]
+    TimedOut,
+    #[error("container failed to start: {0}")]
+    StartFailed(String),
+    #[error("server failed to start ({})", describe_exit(*.exit_code))]
+    ServerFailed {
+        exit_code: Option<i64>,
+        logs: Arc<[String]>,
+    },
+    #[error("incompatible server ({})", describe_server(.version.as_deref(), *.protocol))]
+    IncompatibleServer {
+        version: Option<String>,
+        protocol: Option<u32>,
+    },
+    #[error("model not found: {0}")]
+    ModelNotFound(Model),
+    #[error("lora not found: {0}")]
+    LoraNotFound(LoraId),
+    #[error("server ran out of memory: {0}")]
+    OutOfMemory(String),
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("request was cancelled")]
+    Cancelled,
+    #[error("server error ({kind}): {message}")]
+    ServerError { kind: String, message: String },
     #[error("invalid output: {0}")]
     InvalidOutput(String),
 }
@@ -24,3 +57,39 @@ impl From<serde_json::Error> for Error {
         Self::SerializationFailed(Arc::new(error))
     }
 }
+
+impl From<toml::ser::Error> for Error {
+    fn from(error: toml::ser::Error) -> Self {
+        Self::TomlFailed(error.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Self::TomlFailed(error.to_string())
+    }
+}
+
+fn describe_server(version: Option<&str>, protocol: Option<u32>) -> String {
+    match (version, protocol) {
+        (Some(version), Some(protocol)) => format!(
+            "server {version} speaks protocol {protocol}, expected {}",
+            crate::Server::PROTOCOL_VERSION
+        ),
+        _ => "server does not support version negotiation".to_owned(),
+    }
+}
+
+fn describe_version(version: Option<u64>) -> String {
+    match version {
+        Some(version) => version.to_string(),
+        None => "missing".to_owned(),
+    }
+}
+
+fn describe_exit(exit_code: Option<i64>) -> String {
+    match exit_code {
+        Some(code) => format!("exited with code {code}"),
+        None => "timed out".to_owned(),
+    }
+}
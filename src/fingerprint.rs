@@ -0,0 +1,120 @@
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// Floats are quantized to this many steps per unit before hashing
+const FLOAT_PRECISION: f64 = 10_000.0;
+
+pub(crate) struct Fingerprint {
+    hasher: Sha256,
+}
+
+impl Fingerprint {
+    pub fn new(domain: &str) -> Self {
+        let mut fingerprint = Self {
+            hasher: Sha256::new(),
+        };
+
+        fingerprint.string(domain);
+        fingerprint
+    }
+
+    // Absent and empty values are skipped, so new optional fields keep old fingerprints intact
+    pub fn field(&mut self, name: &str, value: impl Serialize) -> &mut Self {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+
+        if is_empty(&value) {
+            return self;
+        }
+
+        self.string(name);
+        self.value(&value);
+        self
+    }
+
+    pub fn bytes(&mut self, name: &str, bytes: &[u8]) -> &mut Self {
+        self.string(name);
+        self.hasher.update(b"b");
+        self.hasher.update(Sha256::digest(bytes));
+        self
+    }
+
+    pub fn nested(&mut self, name: &str, fingerprint: [u8; 32]) -> &mut Self {
+        self.bytes(name, &fingerprint)
+    }
+
+    pub fn finish(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+
+    fn string(&mut self, string: &str) {
+        self.hasher.update(b"s");
+        self.hasher.update((string.len() as u64).to_be_bytes());
+        self.hasher.update(string.as_bytes());
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Null => {
+                self.hasher.update(b"n");
+            }
+            Value::Bool(bool) => {
+                self.hasher.update(if *bool { b"t" } else { b"f" });
+            }
+            Value::Number(number) => {
+                if let Some(integer) = number.as_i64() {
+                    self.integer(i128::from(integer));
+                } else if let Some(integer) = number.as_u64() {
+                    self.integer(i128::from(integer));
+                } else {
+                    let float = number.as_f64().unwrap_or_default();
+
+                    if float.is_finite() {
+                        self.hasher.update(b"d");
+                        self.hasher
+                            .update(((float * FLOAT_PRECISION).round() as i128).to_be_bytes());
+                    } else {
+                        self.hasher.update(b"x");
+                    }
+                }
+            }
+            Value::String(string) => {
+                self.string(string);
+            }
+            Value::Array(values) => {
+                self.hasher.update(b"a");
+                self.hasher.update((values.len() as u64).to_be_bytes());
+
+                for value in values {
+                    self.value(value);
+                }
+            }
+            Value::Object(map) => {
+                let mut entries: Vec<_> =
+                    map.iter().filter(|(_, value)| !is_empty(value)).collect();
+                entries.sort_by_key(|(key, _)| *key);
+
+                self.hasher.update(b"o");
+                self.hasher.update((entries.len() as u64).to_be_bytes());
+
+                for (key, value) in entries {
+                    self.string(key);
+                    self.value(value);
+                }
+            }
+        }
+    }
+
+    fn integer(&mut self, integer: i128) {
+        self.hasher.update(b"i");
+        self.hasher.update(integer.to_be_bytes());
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(values) => values.is_empty(),
+        _ => false,
+    }
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Guidance(u32);
+
+impl Guidance {
+    pub const RANGE: RangeInclusive<Self> = Self(10)..=Self(200);
+
+    pub fn scale(self) -> f32 {
+        self.0 as f32 / 10.0
+    }
+}
+
+impl Default for Guidance {
+    fn default() -> Self {
+        Self(50)
+    }
+}
+
+impl fmt::Display for Guidance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}", self.scale())
+    }
+}
+
+impl From<u8> for Guidance {
+    fn from(value: u8) -> Self {
+        Self(u32::from(value))
+    }
+}
+
+impl From<Guidance> for f64 {
+    fn from(value: Guidance) -> Self {
+        f64::from(value.0)
+    }
+}
+
+impl num_traits::FromPrimitive for Guidance {
+    fn from_i64(n: i64) -> Option<Self> {
+        u32::try_from(n).ok().map(Self)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        u32::try_from(n).ok().map(Self)
+    }
+}
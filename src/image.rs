@@ -1,16 +1,27 @@
-use crate::server;
-use crate::stream::{SinkExt, Stream};
+use crate::cache;
+use crate::fingerprint::Fingerprint;
+use crate::server::{self, Server};
+use crate::stream::{SinkExt, Stream, StreamExt};
+use crate::transfer::Format;
+use crate::upscaler;
 use crate::{
-    Detail, Error, Inpaint, Lora, Model, Quality, Rectangle, Sampler, Seed, Size, Steps, Upscaler,
+    Detail, Error, Guidance, Inpaint, Job, Lora, Model, Outpaint, Padding, Priority, Quality,
+    Rectangle, Region, Sampler, Seed, SeedStrategy, Size, Steps, Strength, Transfer, Upscaler,
 };
 
 use bytes::Bytes;
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either};
 use serde::{Deserialize, Serialize};
 
 use std::fmt;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicBool};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Image {
+    #[serde(with = "crate::encoding")]
     pub rgba: Bytes,
     pub size: Size,
     pub definition: Definition,
@@ -20,115 +31,448 @@ impl Image {
     pub const DEFAULT_SIZE: Size = Size::new(512, 768);
 
     pub fn generate(
+        server: &Server,
         definition: Definition,
-        preview_after: Option<f32>,
-    ) -> impl Stream<Item = Result<Generation, Error>> {
+        transfer: Transfer,
+        priority: Priority,
+    ) -> (
+        GenerationHandle,
+        impl Stream<Item = Result<Generation, Error>> + use<>,
+    ) {
+        let seed = definition.seed;
+
+        let (handle, batch) = Self::generate_batch(
+            server,
+            definition,
+            1,
+            SeedStrategy::List(vec![seed]),
+            transfer,
+            priority,
+        );
+
+        (
+            handle,
+            batch.map(|generation| generation.map(|(_index, generation)| generation)),
+        )
+    }
+
+    pub fn generate_batch(
+        server: &Server,
+        definition: Definition,
+        count: usize,
+        seeds: SeedStrategy,
+        transfer: Transfer,
+        priority: Priority,
+    ) -> (
+        GenerationHandle,
+        impl Stream<Item = Result<(usize, Generation), Error>> + use<>,
+    ) {
         #[derive(Serialize)]
         struct Request {
             task: &'static str,
-            model: String,
-            prompt: String,
-            negative_prompt: String,
-            size: Size,
-            quality: String,
-            sampler: String,
-            upscaler: Option<Upscaler>,
-            steps: Steps,
-            seed: u64,
-            face_detail: Option<Detail>,
-            hand_detail: Option<Detail>,
-            inpaints: Vec<Inpaint>,
-            loras: Vec<Lora>,
-            preview_after: Option<f32>,
+            job: Job,
+            priority: Priority,
+            #[serde(flatten)]
+            parameters: Parameters,
+            seeds: Vec<u64>,
+            #[serde(flatten)]
+            transfer: Transfer,
         }
 
-        #[derive(Deserialize)]
-        struct Response {
-            width: u32,
-            height: u32,
-            progress: f32,
-            is_final: bool,
-            #[serde(default)]
-            faces: Vec<[f32; 4]>,
-            #[serde(default)]
-            hands: Vec<[f32; 4]>,
+        let job = Job::new();
+        let seeds = seeds.generate(definition.seed, count);
+
+        let request = Request {
+            task: "generate_image",
+            job,
+            priority,
+            parameters: Parameters::new(&definition),
+            seeds: seeds.iter().copied().map(Seed::value).collect(),
+            transfer: transfer.negotiate(server.handshake()),
+        };
+
+        let frames = frames(&definition);
+
+        let definitions = seeds
+            .into_iter()
+            .map(|seed| Definition {
+                seed,
+                ..definition.clone()
+            })
+            .collect();
+
+        run(server, job, request, frames, definitions)
+    }
+
+    pub fn outpaint(
+        server: &Server,
+        image: &Image,
+        outpaint: Outpaint,
+        transfer: Transfer,
+        priority: Priority,
+    ) -> (
+        GenerationHandle,
+        impl Stream<Item = Result<Generation, Error>> + use<>,
+    ) {
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            job: Job,
+            priority: Priority,
+            #[serde(flatten)]
+            parameters: Parameters,
+            image: Size,
+            outpaint: Outpaint,
+            scale: f32,
+            #[serde(flatten)]
+            transfer: Transfer,
         }
 
-        crate::stream::from_future(move |mut sender| async move {
-            let mut stream = server::connect().await?;
-            let mut buffer = Vec::new();
-
-            let request = Request {
-                task: "generate_image",
-                model: definition.model.name().to_owned(),
-                prompt: definition.prompt.clone(),
-                negative_prompt: definition.negative_prompt.clone(),
-                size: definition.size,
-                quality: definition.quality.to_string().to_lowercase(),
-                sampler: match definition.sampler {
-                    Sampler::EulerAncestral => "euler_a",
-                    Sampler::DPMSDEKarras => "dpm++_sde_karras",
-                    Sampler::DPM2MKarras => "dpm++_2m_karras",
-                    Sampler::DPM2MSDEKarras => "dpm++_2m_sde_karras",
-                }
-                .to_owned(),
-                upscaler: definition.upscaler,
-                steps: definition.steps,
-                seed: definition.seed.value(),
-                face_detail: definition.face_detail,
-                hand_detail: definition.hand_detail,
-                inpaints: definition.inpaints.clone(),
-                loras: definition.loras.clone(),
-                preview_after,
-            };
+        let job = Job::new();
 
-            server::send_json(&mut stream, request).await?;
+        let request = Request {
+            task: "outpaint_image",
+            job,
+            priority,
+            parameters: Parameters::base(&image.definition),
+            image: image.size,
+            outpaint: outpaint.clone(),
+            scale: image.definition.scale(image.size),
+            transfer: transfer.negotiate(server.handshake()),
+        };
 
-            loop {
-                let response: Response = server::read_json(&mut stream, &mut buffer).await?;
-                let n_bytes = server::read_bytes(&mut stream, &mut buffer).await?;
+        let mut definition = image.definition.clone();
+        definition.outpaints.push(outpaint);
 
-                let image = {
-                    let rgba = Bytes::from(buffer[..n_bytes].to_vec());
-                    let size = Size::new(response.width, response.height);
+        let (handle, generation) = run(
+            server,
+            job,
+            request,
+            vec![image.rgba.clone()],
+            vec![definition],
+        );
 
-                    Image {
-                        rgba,
-                        size,
-                        definition: definition.clone(),
+        (
+            handle,
+            generation.map(|generation| generation.map(|(_index, generation)| generation)),
+        )
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Parameters {
+    model: String,
+    prompt: String,
+    negative_prompt: String,
+    size: Size,
+    seed: u64,
+    quality: String,
+    sampler: String,
+    upscalers: Vec<Upscaler>,
+    steps: Steps,
+    guidance: f32,
+    face_detail: Option<Detail>,
+    hand_detail: Option<Detail>,
+    inpaints: Vec<InpaintRequest>,
+    outpaints: Vec<Outpaint>,
+    loras: Vec<Lora>,
+    init_image: Option<InitRequest>,
+}
+
+#[derive(Serialize)]
+struct InpaintRequest {
+    region: Option<Rectangle>,
+    mask: Option<Size>,
+    prompt: Option<String>,
+    negative_prompt: Option<String>,
+    strength: Strength,
+    padding: Padding,
+}
+
+#[derive(Serialize)]
+struct InitRequest {
+    width: u32,
+    height: u32,
+    strength: Strength,
+}
+
+impl Parameters {
+    pub(crate) fn base(definition: &Definition) -> Self {
+        Self::new(&Definition {
+            init_image: None,
+            inpaints: Vec::new(),
+            outpaints: Vec::new(),
+            ..definition.clone()
+        })
+    }
+
+    fn new(definition: &Definition) -> Self {
+        Self {
+            model: definition.model.name().to_owned(),
+            prompt: definition.prompt.clone(),
+            negative_prompt: definition.negative_prompt.clone(),
+            size: definition.size,
+            seed: definition.seed.value(),
+            quality: definition.quality.to_string().to_lowercase(),
+            sampler: definition.sampler.id().to_owned(),
+            upscalers: definition.upscalers.clone(),
+            steps: definition.steps,
+            guidance: definition.guidance.scale(),
+            face_detail: definition.face_detail,
+            hand_detail: definition.hand_detail,
+            inpaints: definition
+                .inpaints
+                .iter()
+                .map(|inpaint| {
+                    let (region, mask) = match &inpaint.region {
+                        Region::Rectangle(rectangle) => (Some(*rectangle), None),
+                        Region::Mask(mask) => (None, Some(mask.size())),
+                    };
+
+                    InpaintRequest {
+                        region,
+                        mask,
+                        prompt: inpaint.prompt.clone(),
+                        negative_prompt: inpaint.negative_prompt.clone(),
+                        strength: inpaint.strength,
+                        padding: inpaint.padding,
                     }
-                };
-
-                let _ = sender
-                    .send(if response.is_final {
-                        Generation::Finished {
-                            image,
-                            faces: response
-                                .faces
-                                .into_iter()
-                                .map(Rectangle::from_array)
-                                .collect(),
-                            hands: response
-                                .hands
-                                .into_iter()
-                                .map(Rectangle::from_array)
-                                .collect(),
+                })
+                .collect(),
+            outpaints: definition.outpaints.clone(),
+            loras: definition.loras.clone(),
+            init_image: definition.init_image.as_ref().map(|init| InitRequest {
+                width: init.image.size.width,
+                height: init.image.size.height,
+                strength: init.strength,
+            }),
+        }
+    }
+}
+
+fn frames(definition: &Definition) -> Vec<Bytes> {
+    let init = definition
+        .init_image
+        .iter()
+        .map(|init| init.image.rgba.clone());
+
+    let masks = definition
+        .inpaints
+        .iter()
+        .filter_map(|inpaint| match &inpaint.region {
+            Region::Mask(mask) => Some(mask.luma().clone()),
+            Region::Rectangle(_) => None,
+        });
+
+    init.chain(masks).collect()
+}
+
+pub(crate) fn run<R: Serialize + Send + 'static>(
+    server: &Server,
+    job: Job,
+    request: R,
+    frames: Vec<Bytes>,
+    definitions: Vec<Definition>,
+) -> (
+    GenerationHandle,
+    impl Stream<Item = Result<(usize, Generation), Error>> + use<R>,
+) {
+    #[derive(Serialize)]
+    struct Cancel {
+        task: &'static str,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Response {
+        Queued {
+            queued: Queued,
+        },
+        Cancelled {
+            #[serde(rename = "cancelled")]
+            _cancelled: bool,
+        },
+        Image(Frame),
+    }
+
+    #[derive(Deserialize)]
+    struct Frame {
+        index: usize,
+        width: u32,
+        height: u32,
+        progress: f32,
+        is_final: bool,
+        #[serde(default)]
+        faces: Vec<[f32; 4]>,
+        #[serde(default)]
+        hands: Vec<[f32; 4]>,
+        #[serde(default)]
+        cache: Vec<cache::Lookup>,
+        #[serde(default)]
+        format: Format,
+    }
+
+    #[derive(Deserialize)]
+    struct Queued {
+        position: usize,
+    }
+
+    let server = server.clone();
+    let (cancel, mut cancellations) = mpsc::unbounded::<oneshot::Sender<()>>();
+    let is_started = Arc::new(AtomicBool::new(false));
+
+    let generation = crate::stream::spawn({
+        let is_started = is_started.clone();
+
+        move |mut sender| async move {
+            is_started.store(true, atomic::Ordering::SeqCst);
+
+            if definitions.is_empty() {
+                return Ok(());
+            }
+
+            // Cancelled before being polled, so the server never hears about it
+            if let Ok(_reply) = cancellations.try_recv() {
+                let _ = sender.send((0, Generation::Cancelled)).await;
+                return Ok(());
+            }
+
+            let (writer, mut reader) = server.open().await?;
+
+            server::send_json(&writer, request)?;
+
+            for frame in frames {
+                server::send_bytes(&writer, frame)?;
+            }
+
+            let receive = async {
+                let mut finished = 0;
+
+                while finished < definitions.len() {
+                    let response: Response = server::read_json(&mut reader).await?;
+
+                    let frame = match response {
+                        Response::Queued { queued } => {
+                            let _ = sender
+                                .send((
+                                    finished,
+                                    Generation::Queued {
+                                        position: queued.position,
+                                    },
+                                ))
+                                .await;
+
+                            continue;
                         }
-                    } else {
-                        Generation::Sampling {
-                            image,
-                            progress: response.progress,
+                        Response::Cancelled { .. } => {
+                            let _ = sender.send((finished, Generation::Cancelled)).await;
+                            break;
                         }
-                    })
-                    .await;
+                        Response::Image(frame) => frame,
+                    };
+
+                    let definition = definitions.get(frame.index).cloned().ok_or_else(|| {
+                        Error::InvalidOutput(format!("unknown batch index: {}", frame.index))
+                    })?;
 
-                if response.is_final {
-                    break;
+                    let size = Size::new(frame.width, frame.height);
+                    let rgba = frame
+                        .format
+                        .decode(server::read_bytes(&mut reader).await?, size)?;
+
+                    let image = Image {
+                        rgba,
+                        size,
+                        definition,
+                    };
+
+                    let _ = sender
+                        .send((
+                            frame.index,
+                            if frame.is_final {
+                                Generation::Finished {
+                                    image,
+                                    faces: frame
+                                        .faces
+                                        .into_iter()
+                                        .map(Rectangle::from_array)
+                                        .collect(),
+                                    hands: frame
+                                        .hands
+                                        .into_iter()
+                                        .map(Rectangle::from_array)
+                                        .collect(),
+                                    cache: frame.cache,
+                                }
+                            } else {
+                                Generation::Sampling {
+                                    image,
+                                    progress: frame.progress,
+                                }
+                            },
+                        ))
+                        .await;
+
+                    if frame.is_final {
+                        finished += 1;
+                    }
+                }
+
+                Ok::<_, Error>(())
+            };
+
+            let cancel = async {
+                // Pending replies are resolved when the generation ends
+                let mut replies = Vec::new();
+
+                while let Some(reply) = cancellations.next().await {
+                    if replies.is_empty() {
+                        server::send_json(&writer, Cancel { task: "cancel" })?;
+                    }
+
+                    replies.push(reply);
                 }
+
+                future::pending::<Result<(), Error>>().await
+            };
+
+            match future::select(pin!(receive), pin!(cancel)).await {
+                Either::Left((result, _)) | Either::Right((result, _)) => result,
             }
+        }
+    });
 
-            Ok(())
-        })
+    (
+        GenerationHandle {
+            job,
+            cancel,
+            is_started,
+        },
+        generation,
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct GenerationHandle {
+    job: Job,
+    cancel: mpsc::UnboundedSender<oneshot::Sender<()>>,
+    is_started: Arc<AtomicBool>,
+}
+
+impl GenerationHandle {
+    pub fn job(&self) -> Job {
+        self.job
+    }
+
+    pub async fn cancel(&self) {
+        let (reply, stopped) = oneshot::channel();
+
+        // A generation that has not started is cancelled as soon as it is polled
+        if self.cancel.unbounded_send(reply).is_ok()
+            && self.is_started.load(atomic::Ordering::SeqCst)
+        {
+            let _ = stopped.await;
+        }
     }
 }
 
@@ -144,6 +488,9 @@ impl fmt::Debug for Image {
 
 #[derive(Debug, Clone)]
 pub enum Generation {
+    Queued {
+        position: usize,
+    },
     Sampling {
         image: Image,
         progress: f32,
@@ -152,22 +499,361 @@ pub enum Generation {
         image: Image,
         faces: Vec<Rectangle>,
         hands: Vec<Rectangle>,
+        cache: Vec<cache::Lookup>,
     },
+    Cancelled,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Definition {
     pub model: Model,
     pub prompt: String,
+    #[serde(default)]
     pub negative_prompt: String,
     pub size: Size,
     pub seed: Seed,
+    #[serde(default)]
     pub steps: Steps,
+    #[serde(default)]
+    pub guidance: Guidance,
+    #[serde(default)]
     pub quality: Quality,
+    #[serde(default)]
     pub sampler: Sampler,
-    pub upscaler: Option<Upscaler>,
+    #[serde(default)]
+    pub upscalers: Vec<Upscaler>,
+    #[serde(default)]
     pub face_detail: Option<Detail>,
+    #[serde(default)]
     pub hand_detail: Option<Detail>,
+    #[serde(default)]
     pub inpaints: Vec<Inpaint>,
+    #[serde(default)]
+    pub outpaints: Vec<Outpaint>,
+    #[serde(default)]
     pub loras: Vec<Lora>,
+    #[serde(default)]
+    pub init_image: Option<InitImage>,
+}
+
+impl Definition {
+    // The size before quality and upscaling factors, including every outpaint
+    pub fn canvas(&self) -> Size {
+        self.outpaints
+            .iter()
+            .fold(self.size, |size, outpaint| outpaint.apply(size))
+    }
+
+    pub(crate) fn scale(&self, size: Size) -> f32 {
+        size.width as f32 / self.canvas().width as f32
+    }
+
+    // Hashes the definition through its own encoding, so it stays stable across
+    // protocol versions
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let Self {
+            model,
+            prompt,
+            negative_prompt,
+            size,
+            seed,
+            steps,
+            guidance,
+            quality,
+            sampler,
+            upscalers,
+            face_detail,
+            hand_detail,
+            inpaints,
+            outpaints,
+            loras,
+            init_image,
+        } = self;
+
+        let mut fingerprint = Fingerprint::new("kiroshi::Definition");
+
+        fingerprint
+            .field("model", model.name())
+            .field("prompt", prompt)
+            .field("negative_prompt", negative_prompt)
+            .field("width", size.width)
+            .field("height", size.height)
+            .field("seed", seed.value())
+            .field("steps", steps)
+            .field("guidance", guidance)
+            .field("quality", quality_name(*quality))
+            .field("sampler", sampler_name(*sampler));
+
+        for upscaler in upscalers {
+            fingerprint.nested("upscaler", upscaler_fingerprint(upscaler));
+        }
+
+        if let Some(detail) = face_detail {
+            fingerprint.nested("face_detail", detail_fingerprint(detail));
+        }
+
+        if let Some(detail) = hand_detail {
+            fingerprint.nested("hand_detail", detail_fingerprint(detail));
+        }
+
+        for inpaint in inpaints {
+            fingerprint.nested("inpaint", inpaint_fingerprint(inpaint));
+        }
+
+        for outpaint in outpaints {
+            fingerprint.nested("outpaint", outpaint_fingerprint(outpaint));
+        }
+
+        for lora in loras {
+            let Lora { id, strength } = lora;
+
+            let mut entry = Fingerprint::new("kiroshi::Lora");
+            entry.field("id", id.name()).field("strength", strength);
+
+            fingerprint.nested("lora", entry.finish());
+        }
+
+        if let Some(InitImage { image, strength }) = init_image {
+            let mut entry = Fingerprint::new("kiroshi::InitImage");
+            entry
+                .field("width", image.size.width)
+                .field("height", image.size.height)
+                .bytes("rgba", &image.rgba)
+                .field("strength", strength);
+
+            fingerprint.nested("init_image", entry.finish());
+        }
+
+        fingerprint.finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InitImage {
+    pub image: Arc<Image>,
+    pub strength: Strength,
+}
+
+// Fingerprints name variants explicitly, so renaming them in code or on the wire
+// keeps old fingerprints intact
+fn quality_name(quality: Quality) -> &'static str {
+    match quality {
+        Quality::Low => "low",
+        Quality::Normal => "normal",
+        Quality::High => "high",
+        Quality::Ultra => "ultra",
+        Quality::Insane => "insane",
+    }
+}
+
+fn sampler_name(sampler: Sampler) -> &'static str {
+    match sampler {
+        Sampler::EulerAncestral => "euler_ancestral",
+        Sampler::DPMSDEKarras => "dpm_sde_karras",
+        Sampler::DPM2MKarras => "dpm_2m_karras",
+        Sampler::DPM2MSDEKarras => "dpm_2m_sde_karras",
+    }
+}
+
+fn upscaler_fingerprint(upscaler: &Upscaler) -> [u8; 32] {
+    let Upscaler {
+        model,
+        tile_size,
+        tile_padding,
+    } = upscaler;
+
+    let model = match model {
+        upscaler::Model::RealEsrganX2 => "real_esrgan_x2",
+        upscaler::Model::UltrasharpX4 => "ultrasharp_x4",
+    };
+
+    let mut fingerprint = Fingerprint::new("kiroshi::Upscaler");
+    fingerprint
+        .field("model", model)
+        .field("tile_size", tile_size)
+        .field("tile_padding", tile_padding);
+
+    fingerprint.finish()
+}
+
+fn detail_fingerprint(detail: &Detail) -> [u8; 32] {
+    let Detail {
+        strength,
+        padding,
+        max_area,
+    } = detail;
+
+    let mut fingerprint = Fingerprint::new("kiroshi::Detail");
+    fingerprint
+        .field("strength", strength)
+        .field("padding", padding)
+        .field("max_area", max_area.map(|area| area.value()));
+
+    fingerprint.finish()
+}
+
+fn inpaint_fingerprint(inpaint: &Inpaint) -> [u8; 32] {
+    let Inpaint {
+        region,
+        prompt,
+        negative_prompt,
+        strength,
+        padding,
+    } = inpaint;
+
+    let mut fingerprint = Fingerprint::new("kiroshi::Inpaint");
+
+    match region {
+        Region::Rectangle(Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }) => {
+            fingerprint.field("rectangle", [x, y, width, height]);
+        }
+        Region::Mask(mask) => {
+            fingerprint
+                .field("mask_width", mask.size().width)
+                .field("mask_height", mask.size().height)
+                .bytes("mask_luma", mask.luma());
+        }
+    }
+
+    fingerprint
+        .field("prompt", prompt)
+        .field("negative_prompt", negative_prompt)
+        .field("strength", strength)
+        .field("padding", padding);
+
+    fingerprint.finish()
+}
+
+fn outpaint_fingerprint(outpaint: &Outpaint) -> [u8; 32] {
+    let Outpaint {
+        top,
+        right,
+        bottom,
+        left,
+        prompt,
+        negative_prompt,
+    } = outpaint;
+
+    let mut fingerprint = Fingerprint::new("kiroshi::Outpaint");
+    fingerprint
+        .field("sides", [top, right, bottom, left])
+        .field("prompt", prompt)
+        .field("negative_prompt", negative_prompt);
+
+    fingerprint.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LoraId;
+
+    fn definition() -> Definition {
+        Definition {
+            model: Model::new("sdxl.safetensors"),
+            prompt: "a lighthouse at dusk".to_owned(),
+            negative_prompt: "blurry".to_owned(),
+            size: Size::new(512, 768),
+            seed: Seed::from(42),
+            steps: Steps::default(),
+            guidance: Guidance::default(),
+            quality: Quality::default(),
+            sampler: Sampler::default(),
+            upscalers: Vec::new(),
+            face_detail: Some(Detail::default()),
+            hand_detail: None,
+            inpaints: vec![Inpaint {
+                region: Region::Rectangle(Rectangle::from_array([0.25, 0.25, 0.5, 0.5])),
+                prompt: Some("a boat".to_owned()),
+                negative_prompt: None,
+                strength: Strength::from(80),
+                padding: Padding::from(32),
+            }],
+            outpaints: vec![Outpaint::sides(0, 128, 0, 128)],
+            loras: vec![Lora {
+                id: LoraId::new("pixel-art"),
+                strength: crate::lora::Strength::from(70),
+            }],
+            init_image: None,
+        }
+    }
+
+    fn hex(bytes: [u8; 32]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn consecutive_outpaints_keep_the_quality_scale() {
+        let mut definition = Definition {
+            outpaints: Vec::new(),
+            ..definition()
+        };
+
+        // A first outpaint at 1.25x grows a 512 wide image by 2 * 64 * 1.25
+        definition.outpaints.push(Outpaint::sides(0, 64, 0, 64));
+        assert_eq!(definition.canvas(), Size::new(640, 768));
+        assert_eq!(definition.scale(Size::new(800, 960)), 1.25);
+
+        definition.outpaints.push(Outpaint::sides(128, 0, 0, 0));
+        assert_eq!(definition.canvas(), Size::new(640, 896));
+        assert_eq!(definition.scale(Size::new(800, 1120)), 1.25);
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        assert_eq!(
+            hex(definition().fingerprint()),
+            "ba408c1863b5f079706791581c1e8cae97e9abc17264203f345dfa0c8e1ec98d"
+        );
+    }
+
+    #[test]
+    fn fingerprint_hashes_prompts_verbatim() {
+        let padded = Definition {
+            prompt: "a lighthouse at dusk ".to_owned(),
+            ..definition()
+        };
+
+        assert_ne!(definition().fingerprint(), padded.fingerprint());
+    }
+
+    #[test]
+    fn fingerprint_covers_seed() {
+        let reseeded = Definition {
+            seed: Seed::from(43),
+            ..definition()
+        };
+
+        assert_ne!(definition().fingerprint(), reseeded.fingerprint());
+    }
+
+    #[test]
+    fn fingerprint_distinguishes_qualities_and_samplers() {
+        let mut fingerprints: Vec<_> = Quality::ALL
+            .iter()
+            .map(|&quality| Definition {
+                quality,
+                ..definition()
+            })
+            .chain(Sampler::ALL.iter().map(|&sampler| Definition {
+                quality: Quality::Low,
+                sampler,
+                ..definition()
+            }))
+            .map(|definition| definition.fingerprint())
+            .collect();
+
+        fingerprints.sort();
+        fingerprints.dedup();
+
+        assert_eq!(
+            fingerprints.len(),
+            Quality::ALL.len() + Sampler::ALL.len() - 1
+        );
+    }
 }
@@ -1,12 +1,18 @@
-use crate::{Padding, Rectangle, Strength};
+use crate::{Mask, Padding, Rectangle, Strength};
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Inpaint {
-    pub region: Rectangle,
+    pub region: Region,
     pub prompt: Option<String>,
     pub negative_prompt: Option<String>,
     pub strength: Strength,
     pub padding: Padding,
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Region {
+    Rectangle(Rectangle),
+    Mask(Mask),
+}
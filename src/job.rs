@@ -0,0 +1,73 @@
+use crate::Error;
+use crate::Priority;
+use crate::server::{self, Server};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Job(u64);
+
+impl Job {
+    pub(crate) fn new() -> Self {
+        Self(rand::random())
+    }
+
+    pub async fn reprioritize(self, server: &Server, priority: Priority) -> Result<bool, Error> {
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            job: Job,
+            priority: Priority,
+        }
+
+        self.update(
+            server,
+            Request {
+                task: "reprioritize_job",
+                job: self,
+                priority,
+            },
+        )
+        .await
+    }
+
+    pub async fn remove(self, server: &Server) -> Result<bool, Error> {
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            job: Job,
+        }
+
+        self.update(
+            server,
+            Request {
+                task: "remove_job",
+                job: self,
+            },
+        )
+        .await
+    }
+
+    async fn update(self, server: &Server, request: impl Serialize) -> Result<bool, Error> {
+        let (sender, mut receiver) = server.open().await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            found: bool,
+        }
+
+        server::send_json(&sender, request)?;
+
+        let Response { found } = server::read_json(&mut receiver).await?;
+
+        Ok(found)
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:016x}", self.0)
+    }
+}
@@ -1,6 +1,15 @@
+mod channel;
+mod encoding;
 mod error;
+mod fingerprint;
+mod guidance;
 mod inpaint;
+mod job;
+mod mask;
+mod memory_mode;
+mod outpaint;
 mod padding;
+mod priority;
 mod quality;
 mod rectangle;
 mod sampler;
@@ -10,28 +19,43 @@ mod steps;
 mod stream;
 mod strength;
 
+pub mod cache;
 pub mod detail;
+pub mod detection;
 pub mod image;
+pub mod log;
 pub mod lora;
 pub mod model;
+pub mod project;
 pub mod stats;
+pub mod transfer;
 pub mod upscaler;
 
 pub use detail::Detail;
+pub use detection::{Detection, Detector};
 pub use error::Error;
+pub use guidance::Guidance;
 pub use image::Image;
-pub use lora::Lora;
+pub use log::LogLine;
+pub use lora::{Lora, LoraId};
+pub use mask::Mask;
+pub use memory_mode::MemoryMode;
 pub use strength::Strength;
 pub mod server;
-pub use inpaint::Inpaint;
+pub use inpaint::{Inpaint, Region};
+pub use job::Job;
 pub use model::Model;
+pub use outpaint::{Anchor, Outpaint};
 pub use padding::Padding;
+pub use priority::Priority;
+pub use project::Project;
 pub use quality::Quality;
 pub use rectangle::Rectangle;
 pub use sampler::Sampler;
-pub use seed::Seed;
+pub use seed::{Seed, SeedStrategy};
 pub use server::Server;
 pub use size::Size;
 pub use stats::Stats;
 pub use steps::Steps;
+pub use transfer::{Encoding, Preview, Transfer};
 pub use upscaler::Upscaler;
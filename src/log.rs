@@ -0,0 +1,103 @@
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub timestamp: SystemTime,
+    pub tag: Tag,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Kiroshi,
+    Timing { label: String, duration: Duration },
+    Output,
+}
+
+impl LogLine {
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        let (timestamp, line) = line
+            .split_once(' ')
+            .and_then(|(timestamp, line)| Some((parse_timestamp(timestamp)?, line)))
+            .unwrap_or_else(|| (SystemTime::now(), line));
+
+        if let Some(message) = line.strip_prefix("[kiroshi]") {
+            return Self {
+                timestamp,
+                tag: Tag::Kiroshi,
+                message: message.trim_start().to_owned(),
+            };
+        }
+
+        let timing = line.split_once(": ").and_then(|(label, duration)| {
+            let seconds: f64 = duration.strip_suffix('s')?.parse().ok()?;
+
+            Some(Tag::Timing {
+                label: label.to_owned(),
+                duration: Duration::try_from_secs_f64(seconds).ok()?,
+            })
+        });
+
+        Self {
+            timestamp,
+            tag: timing.unwrap_or(Tag::Output),
+            message: line.to_owned(),
+        }
+    }
+}
+
+// Parses RFC 3339 timestamps, as produced by `docker logs --timestamps`
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.split_once('T')?;
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let year = date.next()?.ok()?;
+    let month = date.next()?.ok()?;
+    let day = date.next()?.ok()?;
+
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else {
+        let split = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(split);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+
+        (
+            time,
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60),
+        )
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let hour = time.next()?.ok()?;
+    let minute = time.next()?.ok()?;
+    let second = time.next()?.ok()?;
+
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        let digits = &fraction[..fraction.len().min(9)];
+
+        digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32)
+    };
+
+    // Days since the Unix epoch (http://howardhinnant.github.io/date_algorithms.html)
+    let days = {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146097 + day_of_era - 719468
+    };
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+
+    SystemTime::UNIX_EPOCH.checked_add(Duration::new(u64::try_from(seconds).ok()?, nanos))
+}
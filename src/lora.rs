@@ -1,15 +1,59 @@
+use crate::Error;
+use crate::server::{self, Server};
+
 use std::fmt;
 use std::ops::RangeInclusive;
-use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Lora {
-    pub file: PathBuf,
+    pub id: LoraId,
     pub strength: Strength,
 }
 
+impl Lora {
+    pub async fn list(server: &Server) -> Result<Vec<LoraId>, Error> {
+        let (sender, mut receiver) = server.open().await?;
+
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            loras: Vec<LoraId>,
+        }
+
+        server::send_json(&sender, Request { task: "list_loras" })?;
+
+        let Response { loras } = server::read_json(&mut receiver).await?;
+
+        Ok(loras)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LoraId(String);
+
+impl LoraId {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for LoraId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Strength(u32);
 
@@ -0,0 +1,81 @@
+use crate::Size;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Encoded")]
+pub struct Mask {
+    #[serde(with = "crate::encoding")]
+    luma: Bytes,
+    size: Size,
+}
+
+#[derive(Deserialize)]
+struct Encoded {
+    #[serde(with = "crate::encoding")]
+    luma: Bytes,
+    size: Size,
+}
+
+impl Mask {
+    pub fn new(size: Size, luma: impl Into<Bytes>) -> Option<Self> {
+        let luma = luma.into();
+
+        if luma.len() as u64 != u64::from(size.width) * u64::from(size.height) {
+            return None;
+        }
+
+        Some(Self { luma, size })
+    }
+
+    pub fn luma(&self) -> &Bytes {
+        &self.luma
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl TryFrom<Encoded> for Mask {
+    type Error = String;
+
+    fn try_from(encoded: Encoded) -> Result<Self, Self::Error> {
+        let length = encoded.luma.len();
+
+        Self::new(encoded.size, encoded.luma).ok_or_else(|| {
+            format!(
+                "mask of {}x{} has {length} bytes of luma",
+                encoded.size.width, encoded.size.height
+            )
+        })
+    }
+}
+
+impl fmt::Debug for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mask")
+            .field("luma", &format!("{} pixels", self.luma.len()))
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luma_must_cover_the_size() {
+        assert!(Mask::new(Size::new(2, 2), vec![0; 4]).is_some());
+        assert!(Mask::new(Size::new(2, 2), vec![0; 3]).is_none());
+    }
+
+    #[test]
+    fn huge_sizes_are_rejected_without_overflowing() {
+        assert!(Mask::new(Size::new(70_000, 70_000), vec![0; 4]).is_none());
+    }
+}
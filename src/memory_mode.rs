@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MemoryMode {
+    #[default]
+    #[serde(rename = "gpu")]
+    Gpu,
+    #[serde(rename = "model_offload")]
+    ModelOffload,
+    #[serde(rename = "sequential_offload")]
+    SequentialOffload,
+}
+
+impl MemoryMode {
+    pub const ALL: &'static [Self] = &[Self::Gpu, Self::ModelOffload, Self::SequentialOffload];
+}
+
+impl fmt::Display for MemoryMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Gpu => "Full GPU",
+            Self::ModelOffload => "Model CPU offload",
+            Self::SequentialOffload => "Sequential CPU offload",
+        })
+    }
+}
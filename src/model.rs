@@ -1,16 +1,17 @@
 use crate::Error;
-use crate::server;
+use crate::server::{self, Server};
 
 use serde::{Deserialize, Serialize};
 
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Model(String);
 
 impl Model {
-    pub async fn list() -> Result<Vec<Self>, Error> {
-        let mut stream = server::connect().await?;
+    pub async fn list(server: &Server) -> Result<Vec<Self>, Error> {
+        let (sender, mut receiver) = server.open().await?;
 
         #[derive(Serialize)]
         struct Request {
@@ -23,19 +24,21 @@ impl Model {
         }
 
         server::send_json(
-            &mut stream,
+            &sender,
             Request {
                 task: "list_models",
             },
-        )
-        .await?;
+        )?;
 
-        let mut buffer = Vec::new();
-        let Response { models } = server::read_json(&mut stream, &mut buffer).await?;
+        let Response { models } = server::read_json(&mut receiver).await?;
 
         Ok(models.into_iter().map(Self).collect())
     }
 
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
     pub fn name(&self) -> &str {
         &self.0
     }
@@ -0,0 +1,84 @@
+use crate::Size;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outpaint {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub prompt: Option<String>,
+    pub negative_prompt: Option<String>,
+}
+
+impl Outpaint {
+    pub fn sides(top: u32, right: u32, bottom: u32, left: u32) -> Self {
+        Self {
+            top,
+            right,
+            bottom,
+            left,
+            prompt: None,
+            negative_prompt: None,
+        }
+    }
+
+    pub fn resize(from: Size, to: Size, anchor: Anchor) -> Self {
+        let width = to.width.saturating_sub(from.width);
+        let height = to.height.saturating_sub(from.height);
+
+        let (left, right) = match anchor {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => (0, width),
+            Anchor::Top | Anchor::Center | Anchor::Bottom => (width / 2, width - width / 2),
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => (width, 0),
+        };
+
+        let (top, bottom) = match anchor {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => (0, height),
+            Anchor::Left | Anchor::Center | Anchor::Right => (height / 2, height - height / 2),
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => (height, 0),
+        };
+
+        Self::sides(top, right, bottom, left)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top == 0 && self.right == 0 && self.bottom == 0 && self.left == 0
+    }
+
+    pub fn apply(&self, size: Size) -> Size {
+        Size::new(
+            size.width + self.left + self.right,
+            size.height + self.top + self.bottom,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const ALL: &[Self] = &[
+        Self::TopLeft,
+        Self::Top,
+        Self::TopRight,
+        Self::Left,
+        Self::Center,
+        Self::Right,
+        Self::BottomLeft,
+        Self::Bottom,
+        Self::BottomRight,
+    ];
+}
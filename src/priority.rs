@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct Priority(i32);
+
+impl Priority {
+    pub const LOW: Self = Self(-10);
+    pub const NORMAL: Self = Self(0);
+    pub const HIGH: Self = Self(10);
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<i32> for Priority {
+    fn from(value: i32) -> Self {
+        Self(value)
+    }
+}
@@ -0,0 +1,367 @@
+use crate::Error;
+use crate::image::{Definition, Image};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::fs;
+
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Project {
+    #[serde(default)]
+    pub definition: Option<Definition>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+impl Project {
+    pub const VERSION: u64 = 2;
+
+    // Each migration upgrades a project from version `n + 1` to `n + 2`
+    const MIGRATIONS: &'static [fn(&mut Value)] = &[version_2];
+
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).await?;
+
+        Self::decode(&contents, Format::detect(path))
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let contents = self.encode(Format::detect(path))?;
+
+        fs::write(path, contents).await?;
+
+        Ok(())
+    }
+
+    pub fn encode(&self, format: Format) -> Result<String, Error> {
+        #[derive(Serialize)]
+        struct File<'a> {
+            version: u64,
+            #[serde(flatten)]
+            project: &'a Project,
+        }
+
+        let file = File {
+            version: Self::VERSION,
+            project: self,
+        };
+
+        match format {
+            Format::Json => Ok(serde_json::to_string_pretty(&file)?),
+            Format::Toml => {
+                let value = to_toml(serde_json::to_value(&file)?)
+                    .ok_or_else(|| Error::TomlFailed("project is empty".to_owned()))?;
+
+                Ok(toml::to_string(&value)?)
+            }
+        }
+    }
+
+    pub fn decode(contents: &str, format: Format) -> Result<Self, Error> {
+        let mut value = match format {
+            Format::Json => serde_json::from_str(contents)?,
+            Format::Toml => serde_json::to_value(toml::from_str::<toml::Value>(contents)?)?,
+        };
+
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or(Error::UnsupportedProject { version: None })?;
+
+        if version == 0 || version > Self::VERSION {
+            return Err(Error::UnsupportedProject {
+                version: Some(version),
+            });
+        }
+
+        migrate(&mut value, version, Self::MIGRATIONS);
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl Format {
+    pub fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+
+fn migrate(project: &mut Value, version: u64, migrations: &[fn(&mut Value)]) {
+    for migration in &migrations[version as usize - 1..] {
+        migration(project);
+    }
+}
+
+fn version_2(project: &mut Value) {
+    upscaler_passes(project);
+    lora_ids(project);
+}
+
+// Version 2 records every upscaling pass instead of a single upscaler
+fn upscaler_passes(project: &mut Value) {
+    definitions(project, &mut |definition| {
+        if let Some(upscaler) = definition.remove("upscaler") {
+            let upscalers = if upscaler.is_null() {
+                Vec::new()
+            } else {
+                vec![upscaler]
+            };
+
+            definition.insert("upscalers".to_owned(), Value::Array(upscalers));
+        }
+    });
+}
+
+// Version 1 referenced LoRAs by host path, which the server resolves by file stem
+fn lora_ids(project: &mut Value) {
+    definitions(project, &mut |definition| {
+        let Some(Value::Array(loras)) = definition.get_mut("loras") else {
+            return;
+        };
+
+        for lora in loras.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(Value::String(file)) = lora.remove("file") {
+                let name = file.rsplit(['/', '\\']).next().unwrap_or(&file);
+                let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+
+                lora.insert("id".to_owned(), Value::String(stem.to_owned()));
+            }
+        }
+    });
+}
+
+// Definitions are nested in images, including their init images
+fn definitions(value: &mut Value, migrate: &mut impl FnMut(&mut Map<String, Value>)) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if let ("definition", Value::Object(definition)) = (key.as_str(), &mut *value) {
+                    migrate(definition);
+                }
+
+                definitions(value, migrate);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                definitions(value, migrate);
+            }
+        }
+        _ => {}
+    }
+}
+
+// TOML has no null and only signed integers, so absent values are dropped
+// and out-of-range integers are stored as strings
+fn to_toml(value: Value) -> Option<toml::Value> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(bool) => toml::Value::Boolean(bool),
+        Value::Number(number) => {
+            if let Some(integer) = number.as_i64() {
+                toml::Value::Integer(integer)
+            } else if let Some(float) = number.as_f64().filter(|_| !number.is_u64()) {
+                toml::Value::Float(float)
+            } else {
+                toml::Value::String(number.to_string())
+            }
+        }
+        Value::String(string) => toml::Value::String(string),
+        Value::Array(values) => {
+            toml::Value::Array(values.into_iter().filter_map(to_toml).collect())
+        }
+        Value::Object(map) => toml::Value::Table(
+            map.into_iter()
+                .filter_map(|(key, value)| Some((key, to_toml(value)?)))
+                .collect(),
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LoraId, Mask, Size};
+
+    use serde_json::json;
+
+    fn definition(seed: u64) -> Value {
+        json!({
+            "model": "sdxl",
+            "prompt": "a lighthouse",
+            "size": { "width": 2, "height": 2 },
+            "seed": seed,
+        })
+    }
+
+    fn project() -> Project {
+        let definition: Definition = serde_json::from_value(definition(u64::MAX)).unwrap();
+
+        Project {
+            definition: Some(Definition {
+                inpaints: vec![crate::Inpaint {
+                    region: crate::Region::Mask(
+                        Mask::new(Size::new(2, 2), vec![0, 64, 128, 255]).unwrap(),
+                    ),
+                    prompt: None,
+                    negative_prompt: Some("fog".to_owned()),
+                    strength: crate::Strength::default(),
+                    padding: crate::Padding::from(8),
+                }],
+                ..definition.clone()
+            }),
+            images: vec![Image {
+                rgba: vec![7; 16].into(),
+                size: Size::new(2, 2),
+                definition,
+            }],
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let encoded = project().encode(Format::Json).unwrap();
+
+        assert_eq!(Project::decode(&encoded, Format::Json).unwrap(), project());
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let encoded = project().encode(Format::Toml).unwrap();
+
+        assert_eq!(Project::decode(&encoded, Format::Toml).unwrap(), project());
+    }
+
+    #[test]
+    fn toml_drops_nulls_and_stringifies_large_integers() {
+        let encoded = project().encode(Format::Toml).unwrap();
+
+        assert!(encoded.contains(&format!("seed = \"{}\"", u64::MAX)));
+        assert!(!encoded.contains("face_detail"));
+        assert!(!encoded.contains("init_image"));
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        for (contents, version) in [
+            (r#"{ "images": [] }"#.to_owned(), None),
+            (r#"{ "version": 0 }"#.to_owned(), Some(0)),
+            (
+                format!(r#"{{ "version": {} }}"#, Project::VERSION + 1),
+                Some(Project::VERSION + 1),
+            ),
+        ] {
+            assert!(matches!(
+                Project::decode(&contents, Format::Json),
+                Err(Error::UnsupportedProject { version: found }) if found == version
+            ));
+        }
+    }
+
+    #[test]
+    fn migrations_run_in_order_from_the_file_version() {
+        fn first(project: &mut Value) {
+            project["applied"] = json!(["first"]);
+        }
+
+        fn second(project: &mut Value) {
+            project["applied"] = json!(["second"]);
+        }
+
+        fn third(project: &mut Value) {
+            project["applied"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!("third"));
+        }
+
+        let migrations: &[fn(&mut Value)] = &[first, second, third];
+
+        let mut project = json!({ "version": 2 });
+        migrate(&mut project, 2, migrations);
+        assert_eq!(project["applied"], json!(["second", "third"]));
+
+        let mut project = json!({ "version": 4, "applied": [] });
+        migrate(&mut project, 4, migrations);
+        assert_eq!(project["applied"], json!([]));
+    }
+
+    #[test]
+    fn version_1_upscalers_become_passes() {
+        let mut upscaled = definition(1);
+        upscaled["upscaler"] =
+            json!({ "model": "4x-ultrasharp", "tile_size": 192, "tile_padding": 24 });
+
+        let mut plain = definition(2);
+        plain["upscaler"] = Value::Null;
+
+        let file = json!({
+            "version": 1,
+            "definition": plain,
+            "images": [{
+                "rgba": "AAAAAA==",
+                "size": { "width": 1, "height": 1 },
+                "definition": upscaled,
+            }],
+        });
+
+        let project = Project::decode(&file.to_string(), Format::Json).unwrap();
+
+        assert_eq!(project.definition.unwrap().upscalers, Vec::new());
+        assert_eq!(project.images[0].definition.upscalers.len(), 1);
+    }
+
+    #[test]
+    fn version_1_lora_files_become_ids() {
+        let mut definition = definition(1);
+        definition["loras"] = json!([
+            { "file": "/home/kiroshi/loras/pixel-art.safetensors", "strength": 70 },
+            { "file": "C:\\loras\\watercolor.safetensors", "strength": 100 },
+            { "id": "already-migrated", "strength": 100 },
+        ]);
+
+        let file = json!({ "version": 1, "definition": definition });
+        let project = Project::decode(&file.to_string(), Format::Json).unwrap();
+
+        let ids: Vec<_> = project
+            .definition
+            .unwrap()
+            .loras
+            .into_iter()
+            .map(|lora| lora.id)
+            .collect();
+
+        assert_eq!(
+            ids,
+            [
+                LoraId::new("pixel-art"),
+                LoraId::new("watercolor"),
+                LoraId::new("already-migrated"),
+            ]
+        );
+    }
+
+    #[test]
+    fn masks_with_wrong_length_are_rejected() {
+        let mut file: Value =
+            serde_json::from_str(&project().encode(Format::Json).unwrap()).unwrap();
+        file["definition"]["inpaints"][0]["region"]["Mask"]["luma"] = json!("AAAA");
+
+        let error = Project::decode(&file.to_string(), Format::Json).unwrap_err();
+
+        assert!(error.to_string().contains("bytes of luma"));
+    }
+}
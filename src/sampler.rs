@@ -18,6 +18,19 @@ impl Sampler {
         Self::DPM2MKarras,
         Self::DPM2MSDEKarras,
     ];
+
+    pub(crate) fn id(self) -> &'static str {
+        match self {
+            Sampler::EulerAncestral => "euler_a",
+            Sampler::DPMSDEKarras => "dpm++_sde_karras",
+            Sampler::DPM2MKarras => "dpm++_2m_karras",
+            Sampler::DPM2MSDEKarras => "dpm++_2m_sde_karras",
+        }
+    }
+
+    pub(crate) fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|sampler| sampler.id() == id)
+    }
 }
 
 impl fmt::Display for Sampler {
@@ -1,8 +1,8 @@
 use std::fmt;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub struct Seed(u64);
 
 impl Seed {
@@ -27,8 +27,67 @@ impl fmt::Display for Seed {
     }
 }
 
+// Seeds may be stored as strings in formats limited to signed integers (e.g. TOML)
+impl<'de> Deserialize<'de> for Seed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Number(u64),
+            String(String),
+        }
+
+        match Value::deserialize(deserializer)? {
+            Value::Number(seed) => Ok(Self(seed)),
+            Value::String(seed) => seed.parse().map(Self).map_err(de::Error::custom),
+        }
+    }
+}
+
 impl From<u64> for Seed {
     fn from(value: u64) -> Self {
         Self(value)
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedStrategy {
+    Sequential,
+    Random,
+    List(Vec<Seed>),
+}
+
+impl SeedStrategy {
+    // A list produces exactly its own seeds, so `count` only applies to the other strategies
+    pub fn generate(&self, first: Seed, count: usize) -> Vec<Seed> {
+        match self {
+            Self::Sequential => (0..count as u64)
+                .map(|i| Seed(first.0.wrapping_add(i)))
+                .collect(),
+            Self::Random => (0..count).map(|_| Seed::random()).collect(),
+            Self::List(seeds) => seeds.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_ignore_the_count() {
+        let seeds = vec![Seed(3), Seed(1), Seed(2)];
+        let strategy = SeedStrategy::List(seeds.clone());
+
+        assert_eq!(strategy.generate(Seed(0), 1), seeds);
+        assert_eq!(strategy.generate(Seed(0), 5), seeds);
+    }
+
+    #[test]
+    fn sequential_seeds_wrap() {
+        assert_eq!(
+            SeedStrategy::Sequential.generate(Seed(u64::MAX), 2),
+            vec![Seed(u64::MAX), Seed(0)]
+        );
+    }
+}
@@ -1,5 +1,13 @@
-use crate::Error;
-
+use crate::channel::{self, Link, Receiver, Sender};
+use crate::log::LogLine;
+use crate::lora::LoraId;
+use crate::stream::{SinkExt, Stream, StreamExt};
+use crate::transfer::Format;
+use crate::upscaler;
+use crate::{Detector, Error, MemoryMode, Model, Sampler};
+
+use bytes::Bytes;
+use futures::lock;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
@@ -8,35 +16,134 @@ use tokio::net;
 use tokio::process;
 use tokio::time;
 
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
-
-const ADDRESS: &str = "127.0.0.1:9149";
+use std::sync::atomic::{self, AtomicBool};
+use std::task;
 
 #[derive(Debug, Clone)]
 pub struct Server {
-    _container: Arc<Container>,
+    endpoint: Endpoint,
+    handshake: Arc<Handshake>,
+    link: Arc<lock::Mutex<Arc<Link>>>,
+    container: Option<Arc<Container>>,
 }
 
 #[derive(Debug)]
-struct Container(String);
+struct Container {
+    id: String,
+    runtime: Runtime,
+    is_removed: AtomicBool,
+}
 
 impl Server {
-    pub async fn run(models_dir: impl AsRef<Path>) -> Result<Server, Error> {
-        let models = {
-            let models_dir = models_dir.as_ref();
-            fs::create_dir_all(&models_dir).await?;
+    pub const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+    pub const SHUTDOWN_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+    pub const PROTOCOL_VERSION: u32 = 3;
 
-            format!("{host}:/models", host = models_dir.to_string_lossy())
-        };
+    pub async fn connect(endpoint: Endpoint) -> Result<Server, Error> {
+        let (handshake, link) = time::timeout(Self::CONNECT_TIMEOUT, ping(&endpoint))
+            .await
+            .map_err(|_| Error::TimedOut)??;
+
+        Ok(Server {
+            endpoint,
+            handshake: Arc::new(handshake),
+            link: Arc::new(lock::Mutex::new(link)),
+            container: None,
+        })
+    }
+
+    pub async fn run(options: ServerOptions) -> Result<Server, Error> {
+        let ServerOptions {
+            models_dir,
+            loras_dir,
+            endpoint,
+            runtime,
+            image,
+            gpus,
+            volumes,
+            env,
+            memory_limit,
+            cache_budget,
+            name,
+            startup_timeout,
+        } = options;
+
+        fs::create_dir_all(&models_dir).await?;
+
+        let mut command = process::Command::new(runtime.program());
+
+        command.arg("create").arg("-t");
+
+        if let Some(name) = &name {
+            command.args(["--name", name]);
+        }
+
+        match &gpus {
+            Gpus::All => {
+                command.args(["--gpus", "all"]);
+            }
+            Gpus::Devices(devices) => {
+                let devices = devices
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                command.args(["--gpus", &format!("\"device={devices}\"")]);
+            }
+        }
+
+        if let Some(mebibytes) = memory_limit {
+            command.args(["--memory", &format!("{mebibytes}m")]);
+        }
 
-        let mut process = process::Command::new("docker")
-            .arg("create")
-            .args(["-t", "--rm"])
-            .args(["--gpus", "all"])
-            .args(["-p", "9149:9149"])
-            .args(["-v", &models])
-            .arg("ghcr.io/hecrj/kiroshi/server:latest")
+        command.args(["-v", &volume(&models_dir, "/models")]);
+
+        if let Some(loras_dir) = &loras_dir {
+            fs::create_dir_all(loras_dir).await?;
+
+            command.args(["-v", &volume(loras_dir, "/loras")]);
+        }
+
+        for (host, container) in &volumes {
+            fs::create_dir_all(host).await?;
+
+            command.args(["-v", &volume(host, container)]);
+        }
+
+        for (key, value) in &env {
+            command.args(["-e", &format!("{key}={value}")]);
+        }
+
+        if let Some(mebibytes) = cache_budget {
+            command.args(["-e", &format!("KIROSHI_CACHE_BUDGET={mebibytes}")]);
+        }
+
+        match &endpoint {
+            Endpoint::Tcp { host, port } => {
+                command.args(["-p", &publish(host, *port)]);
+            }
+            Endpoint::Unix(path) => {
+                let directory = path.parent().unwrap_or(Path::new("."));
+                let file = path.file_name().ok_or(Error::DockerFailed)?;
+
+                fs::create_dir_all(directory).await?;
+
+                command
+                    .args(["-v", &volume(directory, "/run/kiroshi")])
+                    .args([
+                        "-e",
+                        &format!("KIROSHI_SOCKET=/run/kiroshi/{}", file.to_string_lossy()),
+                    ]);
+            }
+        }
+
+        let mut process = command
+            .arg(&image)
             .stdout(std::process::Stdio::piped())
             .stdin(std::process::Stdio::null())
             .stderr(std::process::Stdio::null())
@@ -49,26 +156,302 @@ impl Server {
 
             let mut lines = output.lines();
 
-            lines.next_line().await?.ok_or(Error::DockerFailed)?
+            Container {
+                id: lines.next_line().await?.ok_or(Error::DockerFailed)?,
+                runtime,
+                is_removed: AtomicBool::new(false),
+            }
         };
 
-        let _start = process::Command::new("docker")
-            .args(["start", &container])
+        let start = process::Command::new(runtime.program())
+            .args(["start", &container.id])
+            .stdin(std::process::Stdio::null())
             .output()
             .await?;
 
-        let _logs = process::Command::new("docker")
-            .args(["logs", "-f", &container])
-            .spawn()?;
+        if !start.status.success() {
+            return Err(Error::StartFailed(
+                String::from_utf8_lossy(&start.stderr).trim().to_owned(),
+            ));
+        }
 
         // Wait until server is accepting connections
-        while ping().await.is_err() {
+        let deadline = time::Instant::now() + startup_timeout;
+
+        let (handshake, link) = loop {
+            match time::timeout(time::Duration::from_secs(1), ping(&endpoint)).await {
+                Ok(Ok(connection)) => break connection,
+                Ok(Err(error @ Error::IncompatibleServer { .. })) => return Err(error),
+                _ => {}
+            }
+
+            if let Some(exit_code) = container.exit_code().await? {
+                return Err(Error::ServerFailed {
+                    exit_code: Some(exit_code),
+                    logs: container.tail(Container::TAIL_LINES).await?.into(),
+                });
+            }
+
+            if time::Instant::now() >= deadline {
+                return Err(Error::ServerFailed {
+                    exit_code: None,
+                    logs: container.tail(Container::TAIL_LINES).await?.into(),
+                });
+            }
+
             time::sleep(time::Duration::from_millis(500)).await;
-        }
+        };
 
         Ok(Server {
-            _container: Arc::new(Container(container)),
+            endpoint,
+            handshake: Arc::new(handshake),
+            link: Arc::new(lock::Mutex::new(link)),
+            container: Some(Arc::new(container)),
+        })
+    }
+
+    pub async fn shutdown(self) -> Result<Shutdown, Error> {
+        let Some(container) = &self.container else {
+            return Ok(Shutdown::Detached);
+        };
+
+        container.signal("SIGTERM").await?;
+
+        let shutdown = match time::timeout(Self::SHUTDOWN_TIMEOUT, container.wait()).await {
+            Ok(exit_code) => Shutdown::Graceful {
+                exit_code: exit_code?,
+            },
+            Err(_) => {
+                container.signal("SIGKILL").await?;
+                let _ = container.wait().await?;
+
+                Shutdown::Killed
+            }
+        };
+
+        container.remove().await?;
+
+        Ok(shutdown)
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    pub fn logs(&self) -> impl Stream<Item = LogLine> + use<> {
+        let container = self
+            .container
+            .as_ref()
+            .map(|container| (container.runtime, container.id.clone()));
+
+        crate::stream::from_future(move |mut sender| async move {
+            use io::AsyncBufReadExt;
+
+            let Some((runtime, id)) = container else {
+                return Ok(());
+            };
+
+            let mut process = process::Command::new(runtime.program())
+                .args(["logs", "-f", "--timestamps", &id])
+                .stdout(std::process::Stdio::piped())
+                .stdin(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .spawn()?;
+
+            let output = io::BufReader::new(process.stdout.take().expect("piped stdout"));
+            let mut lines = output.lines();
+
+            while let Some(line) = lines.next_line().await? {
+                if sender.send(LogLine::parse(&line)).await.is_err() {
+                    break;
+                }
+            }
+
+            let _ = process.wait().await?;
+
+            Ok::<_, Error>(())
         })
+        .filter_map(|line| async move { line.ok() })
+    }
+
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    pub async fn ping(&self) -> Result<Handshake, Error> {
+        let (sender, mut receiver) = self.open().await?;
+
+        send_json(&sender, Ping { task: "ping" })?;
+
+        handshake(read_json(&mut receiver).await?)
+    }
+
+    pub async fn set_memory_mode(&self, memory_mode: MemoryMode) -> Result<(), Error> {
+        let (sender, mut receiver) = self.open().await?;
+
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            memory_mode: MemoryMode,
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "memory_mode")]
+            _memory_mode: MemoryMode,
+        }
+
+        send_json(
+            &sender,
+            Request {
+                task: "set_memory_mode",
+                memory_mode,
+            },
+        )?;
+
+        let Response { .. } = read_json(&mut receiver).await?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn open(&self) -> Result<(Sender, Receiver), Error> {
+        let mut link = self.link.lock().await;
+
+        if !link.is_alive() {
+            *link = Link::connect(&self.endpoint).await?;
+        }
+
+        Ok(link.channel())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: String,
+    pub protocol: u32,
+    pub tasks: Vec<String>,
+    pub samplers: Vec<Sampler>,
+    pub upscalers: Vec<upscaler::Model>,
+    pub detectors: Vec<Detector>,
+    pub formats: Vec<Format>,
+}
+
+impl Handshake {
+    pub fn supports(&self, task: &str) -> bool {
+        self.tasks.iter().any(|supported| supported == task)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    Graceful { exit_code: i64 },
+    Killed,
+    Detached,
+}
+
+impl Container {
+    const TAIL_LINES: usize = 20;
+
+    async fn signal(&self, signal: &str) -> Result<(), Error> {
+        let kill = process::Command::new(self.runtime.program())
+            .args(["kill", "--signal", signal, &self.id])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await?;
+
+        // The container may have exited already
+        if !kill.success() && self.exit_code().await?.is_none() {
+            return Err(Error::DockerFailed);
+        }
+
+        Ok(())
+    }
+
+    async fn wait(&self) -> Result<i64, Error> {
+        let wait = process::Command::new(self.runtime.program())
+            .args(["wait", &self.id])
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        if !wait.status.success() {
+            return Err(Error::DockerFailed);
+        }
+
+        String::from_utf8_lossy(&wait.stdout)
+            .trim()
+            .parse()
+            .map_err(|_| {
+                Error::InvalidOutput(format!(
+                    "unexpected output by {runtime} wait",
+                    runtime = self.runtime.program()
+                ))
+            })
+    }
+
+    async fn remove(&self) -> Result<(), Error> {
+        let rm = process::Command::new(self.runtime.program())
+            .args(["rm", "--force", &self.id])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await?;
+
+        if !rm.success() {
+            return Err(Error::DockerFailed);
+        }
+
+        self.is_removed.store(true, atomic::Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    async fn exit_code(&self) -> Result<Option<i64>, Error> {
+        let inspect = process::Command::new(self.runtime.program())
+            .args([
+                "inspect",
+                "--format",
+                "{{.State.Status}} {{.State.ExitCode}}",
+            ])
+            .arg(&self.id)
+            .output()
+            .await?;
+
+        if !inspect.status.success() {
+            return Err(Error::DockerFailed);
+        }
+
+        let output = String::from_utf8_lossy(&inspect.stdout);
+        let mut state = output.split_whitespace();
+
+        match (state.next(), state.next()) {
+            (Some("exited" | "dead"), Some(exit_code)) => Ok(exit_code.parse().ok()),
+            (Some(_), Some(_)) => Ok(None),
+            _ => Err(Error::InvalidOutput(format!(
+                "unexpected output by {runtime} inspect",
+                runtime = self.runtime.program()
+            ))),
+        }
+    }
+
+    async fn tail(&self, lines: usize) -> Result<Vec<String>, Error> {
+        let logs = process::Command::new(self.runtime.program())
+            .args(["logs", "--tail", &lines.to_string()])
+            .arg(&self.id)
+            .output()
+            .await?;
+
+        Ok(String::from_utf8_lossy(&logs.stdout)
+            .lines()
+            .chain(String::from_utf8_lossy(&logs.stderr).lines())
+            .map(|line| line.trim_end().to_owned())
+            .collect())
     }
 }
 
@@ -76,8 +459,34 @@ impl Drop for Container {
     fn drop(&mut self) {
         use std::process;
 
-        let _ = process::Command::new("docker")
-            .args(["stop", &self.0])
+        if self.is_removed.load(atomic::Ordering::Relaxed) {
+            return;
+        }
+
+        let program = self.runtime.program();
+        let script = format!(
+            "{program} stop {id}; {program} rm --force {id}",
+            id = self.id
+        );
+
+        // A single detached shell stops gracefully and then removes the container,
+        // even if this process exits right after
+        #[cfg(unix)]
+        let mut command = process::Command::new("sh");
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            // Keep interrupts sent to our process group from cutting the cleanup short
+            let _ = command.arg("-c").arg(script).process_group(0);
+        }
+
+        #[cfg(not(unix))]
+        let mut command = process::Command::new("cmd");
+        #[cfg(not(unix))]
+        command.arg("/C").arg(script.replace(';', " &"));
+
+        let _ = command
             .stdin(process::Stdio::null())
             .stdout(process::Stdio::null())
             .stderr(process::Stdio::null())
@@ -85,59 +494,405 @@ impl Drop for Container {
     }
 }
 
-pub async fn connect() -> Result<net::TcpStream, Error> {
-    Ok(net::TcpStream::connect(ADDRESS).await?)
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    models_dir: PathBuf,
+    loras_dir: Option<PathBuf>,
+    endpoint: Endpoint,
+    runtime: Runtime,
+    image: String,
+    gpus: Gpus,
+    volumes: Vec<(PathBuf, String)>,
+    env: Vec<(String, String)>,
+    memory_limit: Option<u64>,
+    cache_budget: Option<u64>,
+    name: Option<String>,
+    startup_timeout: time::Duration,
 }
 
-async fn ping() -> Result<(), Error> {
-    let mut stream = connect().await?;
+impl ServerOptions {
+    pub const DEFAULT_IMAGE: &'static str = "ghcr.io/hecrj/kiroshi/server:latest";
+    pub const DEFAULT_STARTUP_TIMEOUT: time::Duration = time::Duration::from_secs(120);
+
+    pub fn new(models_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            models_dir: models_dir.into(),
+            loras_dir: None,
+            endpoint: Endpoint::default(),
+            runtime: Runtime::default(),
+            image: Self::DEFAULT_IMAGE.to_owned(),
+            gpus: Gpus::default(),
+            volumes: Vec::new(),
+            env: Vec::new(),
+            memory_limit: None,
+            cache_budget: None,
+            name: None,
+            startup_timeout: Self::DEFAULT_STARTUP_TIMEOUT,
+        }
+    }
+
+    pub fn loras_dir(mut self, loras_dir: impl Into<PathBuf>) -> Self {
+        self.loras_dir = Some(loras_dir.into());
+        self
+    }
 
-    #[derive(Serialize)]
-    struct Request {
-        task: &'static str,
+    pub fn endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = endpoint;
+        self
     }
 
-    #[derive(Deserialize)]
-    struct Response(bool);
+    pub fn runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = image.into();
+        self
+    }
 
-    send_json(&mut stream, Request { task: "ping" }).await?;
+    pub fn gpus(mut self, gpus: Gpus) -> Self {
+        self.gpus = gpus;
+        self
+    }
+
+    pub fn volume(mut self, host: impl Into<PathBuf>, container: impl Into<String>) -> Self {
+        self.volumes.push((host.into(), container.into()));
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn memory_limit(mut self, mebibytes: u64) -> Self {
+        self.memory_limit = Some(mebibytes);
+        self
+    }
+
+    pub fn cache_budget(mut self, mebibytes: u64) -> Self {
+        self.cache_budget = Some(mebibytes);
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn startup_timeout(mut self, startup_timeout: time::Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+}
 
-    let mut buffer = Vec::new();
-    let Response(_pong) = read_json(&mut stream, &mut buffer).await?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Runtime {
+    #[default]
+    Docker,
+    Podman,
+}
 
-    Ok(())
+impl Runtime {
+    fn program(self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        }
+    }
 }
 
-pub async fn read_bytes(stream: &mut net::TcpStream, buffer: &mut Vec<u8>) -> Result<usize, Error> {
-    use tokio::io::AsyncReadExt;
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Gpus {
+    #[default]
+    All,
+    Devices(Vec<u32>),
+}
 
-    let message_size = stream.read_u64().await? as usize;
+fn volume(host: &Path, container: &str) -> String {
+    format!("{host}:{container}", host = host.to_string_lossy())
+}
 
-    if buffer.len() < message_size {
-        buffer.resize(message_size, 0);
+fn publish(host: &str, port: u16) -> String {
+    match host {
+        "" => format!("{port}:9149"),
+        host if host.contains(':') && !host.starts_with('[') => format!("[{host}]:{port}:9149"),
+        host => format!("{host}:{port}:9149"),
     }
+}
 
-    Ok(stream.read_exact(&mut buffer[..message_size]).await?)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
 }
 
-pub async fn read_json<T: DeserializeOwned>(
-    stream: &mut net::TcpStream,
-    buffer: &mut Vec<u8>,
-) -> Result<T, Error> {
-    let message_size = read_bytes(stream, buffer).await?;
-    let data = serde_json::from_reader(&buffer[..message_size])?;
+impl Endpoint {
+    pub const DEFAULT_PORT: u16 = 9149;
+
+    pub fn tcp(host: impl Into<String>, port: u16) -> Self {
+        Self::Tcp {
+            host: host.into(),
+            port,
+        }
+    }
 
-    Ok(data)
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self::Unix(path.into())
+    }
 }
 
-pub async fn send_json<T: Serialize>(stream: &mut net::TcpStream, data: T) -> Result<(), Error> {
-    use tokio::io::AsyncWriteExt;
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self::tcp("127.0.0.1", Self::DEFAULT_PORT)
+    }
+}
 
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { host, port } => write!(f, "{host}:{port}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Connection(Transport);
+
+#[derive(Debug)]
+enum Transport {
+    Tcp(net::TcpStream),
+    #[cfg(unix)]
+    Unix(net::UnixStream),
+}
+
+impl Connection {
+    pub async fn open(endpoint: &Endpoint) -> Result<Self, Error> {
+        let transport = match endpoint {
+            Endpoint::Tcp { host, port } => {
+                Transport::Tcp(net::TcpStream::connect((host.as_str(), *port)).await?)
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Transport::Unix(net::UnixStream::connect(path).await?),
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => {
+                return Err(io::Error::from(io::ErrorKind::Unsupported).into());
+            }
+        };
+
+        Ok(Self(transport))
+    }
+}
+
+impl io::AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut io::ReadBuf<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        match &mut self.get_mut().0 {
+            Transport::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Transport::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl io::AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        match &mut self.get_mut().0 {
+            Transport::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Transport::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<io::Result<()>> {
+        match &mut self.get_mut().0 {
+            Transport::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Transport::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        match &mut self.get_mut().0 {
+            Transport::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Transport::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+async fn ping(endpoint: &Endpoint) -> Result<(Handshake, Arc<Link>), Error> {
+    // The handshake is a single unmultiplexed request, so a server speaking any
+    // protocol version can answer it and be reported as incompatible
+    let request = serde_json::to_vec(&Ping { task: "ping" })?;
+
+    let response = match channel::request(endpoint, &request).await {
+        Err(Error::IOFailed(error)) if error.kind() == io::ErrorKind::InvalidData => {
+            return Err(Error::IncompatibleServer {
+                version: None,
+                protocol: None,
+            });
+        }
+        response => response?,
+    };
+
+    let handshake = handshake(decode_json(&response)?)?;
+    let link = Link::connect(endpoint).await?;
+
+    Ok((handshake, link))
+}
+
+#[derive(Serialize)]
+struct Ping {
+    task: &'static str,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Pong {
+    Handshake {
+        version: String,
+        protocol: u32,
+        // Capabilities vary between protocol versions, which are rejected below
+        #[serde(default)]
+        tasks: Vec<String>,
+        #[serde(default)]
+        samplers: Vec<String>,
+        #[serde(default)]
+        upscalers: Vec<String>,
+        #[serde(default)]
+        detectors: Vec<String>,
+        #[serde(default)]
+        formats: Vec<String>,
+    },
+    Legacy(serde::de::IgnoredAny),
+}
+
+fn handshake(response: Pong) -> Result<Handshake, Error> {
+    let Pong::Handshake {
+        version,
+        protocol,
+        tasks,
+        samplers,
+        upscalers,
+        detectors,
+        formats,
+    } = response
+    else {
+        return Err(Error::IncompatibleServer {
+            version: None,
+            protocol: None,
+        });
+    };
+
+    if protocol != Server::PROTOCOL_VERSION {
+        return Err(Error::IncompatibleServer {
+            version: Some(version),
+            protocol: Some(protocol),
+        });
+    }
+
+    // Unknown capabilities are from a newer server; the client cannot request them anyway
+    Ok(Handshake {
+        version,
+        protocol,
+        tasks,
+        samplers: samplers
+            .iter()
+            .filter_map(|sampler| Sampler::from_id(sampler))
+            .collect(),
+        upscalers: upscalers
+            .into_iter()
+            .filter_map(|upscaler| serde_json::from_value(serde_json::Value::String(upscaler)).ok())
+            .collect(),
+        detectors: detectors
+            .into_iter()
+            .filter_map(|detector| serde_json::from_value(serde_json::Value::String(detector)).ok())
+            .collect(),
+        formats: formats
+            .into_iter()
+            .filter_map(|format| serde_json::from_value(serde_json::Value::String(format)).ok())
+            .collect(),
+    })
+}
+
+pub(crate) async fn read_bytes(receiver: &mut Receiver) -> Result<Bytes, Error> {
+    receiver.receive().await
+}
+
+pub(crate) async fn read_json<T: DeserializeOwned>(receiver: &mut Receiver) -> Result<T, Error> {
+    decode_json(&receiver.receive().await?)
+}
+
+fn decode_json<T: DeserializeOwned>(message: &[u8]) -> Result<T, Error> {
+    #[derive(Deserialize)]
+    struct Failure {
+        kind: String,
+        message: String,
+        #[serde(default)]
+        model: Option<Model>,
+        #[serde(default)]
+        lora: Option<LoraId>,
+    }
+
+    let mut message: serde_json::Value = serde_json::from_slice(message)?;
+
+    if let Some(error) = message
+        .as_object_mut()
+        .and_then(|message| message.remove("error"))
+    {
+        let failure: Failure = serde_json::from_value(error)?;
+
+        return Err(match failure.kind.as_str() {
+            "model_not_found" => {
+                Error::ModelNotFound(failure.model.unwrap_or_else(|| Model::new(failure.message)))
+            }
+            "lora_not_found" => {
+                Error::LoraNotFound(failure.lora.unwrap_or_else(|| LoraId::new(failure.message)))
+            }
+            "out_of_memory" => Error::OutOfMemory(failure.message),
+            "invalid_request" => Error::InvalidRequest(failure.message),
+            "cancelled" => Error::Cancelled,
+            _ => Error::ServerError {
+                kind: failure.kind,
+                message: failure.message,
+            },
+        });
+    }
+
+    Ok(serde_json::from_value(message)?)
+}
+
+pub(crate) fn send_json<T: Serialize>(sender: &Sender, data: T) -> Result<(), Error> {
     let bytes = serde_json::to_vec(&data)?;
 
-    stream.write_u64(bytes.len() as u64).await?;
-    stream.write_all(bytes.as_slice()).await?;
-    stream.flush().await?;
+    send_bytes(sender, bytes)
+}
+
+pub(crate) fn send_bytes(sender: &Sender, bytes: impl Into<Bytes>) -> Result<(), Error> {
+    sender.send(bytes.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn published_ports_bind_to_the_endpoint_host() {
+        assert_eq!(publish("127.0.0.1", 9000), "127.0.0.1:9000:9149");
+        assert_eq!(publish("::1", 9000), "[::1]:9000:9149");
+        assert_eq!(publish("", 9000), "9000:9149");
+    }
 }
@@ -5,6 +5,8 @@ use futures::Future;
 use futures::channel::mpsc;
 use futures::stream;
 
+use std::pin::pin;
+
 pub fn from_future<T, E, F>(
     f: impl FnOnce(mpsc::Sender<T>) -> F,
 ) -> impl Stream<Item = Result<T, E>>
@@ -23,3 +25,45 @@ where
         }),
     )
 }
+
+// Drives the stream on its own task once first polled, so it keeps making progress
+// even while nobody polls it
+pub fn spawn<T, E, F>(
+    f: impl FnOnce(mpsc::Sender<T>) -> F + Send + 'static,
+) -> impl Stream<Item = Result<T, E>>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+    F: Future<Output = Result<(), E>> + Send + 'static,
+{
+    struct Abort(tokio::task::AbortHandle);
+
+    impl Drop for Abort {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+
+    stream::once(async move {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let task = tokio::spawn(async move {
+            let mut stream = pin!(from_future(f));
+
+            while let Some(item) = stream.next().await {
+                if sender.unbounded_send(item).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Dropping the stream stops the task
+        let abort = Abort(task.abort_handle());
+
+        receiver.map(move |item| {
+            let _ = &abort;
+            item
+        })
+    })
+    .flatten()
+}
@@ -0,0 +1,148 @@
+use crate::server::Handshake;
+use crate::{Error, Size};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Transfer {
+    pub preview: Option<Preview>,
+    pub encoding: Encoding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Preview {
+    pub after: f32,
+    pub encoding: Encoding,
+    pub max_dimension: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Raw,
+    Png,
+    Jpeg {
+        quality: u8,
+    },
+    Webp {
+        quality: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Raw,
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl Transfer {
+    // Encodings the server cannot produce fall back to raw frames
+    pub(crate) fn negotiate(self, handshake: &Handshake) -> Self {
+        let negotiate = |encoding: Encoding| {
+            if handshake.formats.contains(&encoding.format()) {
+                encoding
+            } else {
+                Encoding::Raw
+            }
+        };
+
+        Self {
+            preview: self.preview.map(|preview| Preview {
+                encoding: negotiate(preview.encoding),
+                ..preview
+            }),
+            encoding: negotiate(self.encoding),
+        }
+    }
+}
+
+impl Encoding {
+    pub const DEFAULT_QUALITY: u8 = 85;
+
+    pub fn format(self) -> Format {
+        match self {
+            Self::Raw => Format::Raw,
+            Self::Png => Format::Png,
+            Self::Jpeg { .. } => Format::Jpeg,
+            Self::Webp { .. } => Format::Webp,
+        }
+    }
+}
+
+impl Format {
+    pub const ALL: &[Self] = &[Self::Raw, Self::Png, Self::Jpeg, Self::Webp];
+
+    pub(crate) fn decode(self, data: Bytes, size: Size) -> Result<Bytes, Error> {
+        let format = match self {
+            Self::Raw => {
+                let length = u64::from(size.width) * u64::from(size.height) * 4;
+
+                if data.len() as u64 != length {
+                    return Err(Error::InvalidOutput(format!(
+                        "expected {length} bytes of rgba, got {}",
+                        data.len()
+                    )));
+                }
+
+                return Ok(data);
+            }
+            Self::Png => image::ImageFormat::Png,
+            Self::Jpeg => image::ImageFormat::Jpeg,
+            Self::Webp => image::ImageFormat::WebP,
+        };
+
+        let decoded = image::load_from_memory_with_format(&data, format)
+            .map_err(|error| Error::InvalidOutput(format!("{self} decoding failed: {error}")))?;
+
+        if decoded.width() != size.width || decoded.height() != size.height {
+            return Err(Error::InvalidOutput(format!(
+                "expected a {}x{} {self} image, got {}x{}",
+                size.width,
+                size.height,
+                decoded.width(),
+                decoded.height()
+            )));
+        }
+
+        Ok(Bytes::from(decoded.into_rgba8().into_raw()))
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Raw => "raw",
+            Self::Png => "png",
+            Self::Jpeg => "jpeg",
+            Self::Webp => "webp",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_frames_must_cover_the_size() {
+        let size = Size::new(2, 2);
+
+        assert!(Format::Raw.decode(Bytes::from(vec![0; 16]), size).is_ok());
+        assert!(Format::Raw.decode(Bytes::from(vec![0; 15]), size).is_err());
+    }
+
+    #[test]
+    fn huge_raw_sizes_are_rejected_without_overflowing() {
+        let result = Format::Raw.decode(Bytes::from(vec![0; 16]), Size::new(70_000, 70_000));
+
+        assert!(matches!(result, Err(Error::InvalidOutput(_))));
+    }
+}
@@ -1,17 +1,74 @@
-use crate::Padding;
+use crate::image::{self, Generation, GenerationHandle};
+use crate::stream::{Stream, StreamExt};
+use crate::{Encoding, Error, Image, Job, Padding, Priority, Server, Size, Transfer};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use std::fmt;
 use std::ops::RangeInclusive;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Upscaler {
     pub model: Model,
     pub tile_size: TileSize,
     pub tile_padding: Padding,
 }
 
+impl Upscaler {
+    pub fn apply(
+        self,
+        server: &Server,
+        image: &Image,
+        encoding: Encoding,
+        priority: Priority,
+    ) -> (
+        GenerationHandle,
+        impl Stream<Item = Result<Generation, Error>> + use<>,
+    ) {
+        #[derive(Serialize)]
+        struct Request {
+            task: &'static str,
+            job: Job,
+            priority: Priority,
+            image: Size,
+            upscaler: Upscaler,
+            #[serde(flatten)]
+            transfer: Transfer,
+        }
+
+        let job = Job::new();
+
+        let request = Request {
+            task: "upscale_image",
+            job,
+            priority,
+            image: image.size,
+            upscaler: self,
+            transfer: Transfer {
+                preview: None,
+                encoding,
+            }
+            .negotiate(server.handshake()),
+        };
+
+        let mut definition = image.definition.clone();
+        definition.upscalers.push(self);
+
+        let (handle, generation) = image::run(
+            server,
+            job,
+            request,
+            vec![image.rgba.clone()],
+            vec![definition],
+        );
+
+        (
+            handle,
+            generation.map(|generation| generation.map(|(_index, generation)| generation)),
+        )
+    }
+}
+
 impl Default for Upscaler {
     fn default() -> Self {
         Self {
@@ -22,7 +79,7 @@ impl Default for Upscaler {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Model {
     #[serde(rename = "2x-real_esrgan")]
     RealEsrganX2,
@@ -43,7 +100,7 @@ impl fmt::Display for Model {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct TileSize(u32);
 
 impl TileSize {